@@ -0,0 +1,247 @@
+use std::ffi::{CStr, CString};
+
+#[cfg(feature = "compiler")]
+use crate::compile::Compiler;
+use crate::{
+    conversion::{FromLuauMulti, IntoLuauMulti},
+    ffi::prelude::*,
+    Luau, LuauError, LuauFunction, LuauTable,
+};
+
+/// How the contents of a `Chunk` are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkMode {
+    /// Luau source which is compiled before loading
+    #[cfg(feature = "compiler")]
+    Text,
+    /// Bytecode produced by the compiler
+    Binary,
+    /// Bytecode if the first byte is a control character, which source text never starts with, otherwise source
+    #[default]
+    Auto,
+}
+
+/// Builder for loading and running a chunk of source or bytecode, created by `Luau::chunk`
+pub struct Chunk<'a> {
+    luau: &'a Luau,
+    contents: Vec<u8>,
+    name: CString,
+    environment: Option<LuauTable>,
+    #[cfg(feature = "compiler")]
+    compiler: Compiler,
+    mode: ChunkMode,
+}
+
+impl Chunk<'_> {
+    /// Sets the chunk name used in error messages and tracebacks, such as `@path/to/file.luau` or `=name`
+    pub fn set_name(mut self, name: &CStr) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Sets the table used as the global environment of the chunk instead of the globals of the state
+    pub fn set_environment(mut self, environment: LuauTable) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Sets the compiler used for source chunks
+    #[cfg(feature = "compiler")]
+    pub fn set_compiler(mut self, compiler: Compiler) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    pub fn set_mode(mut self, mode: ChunkMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn is_binary(&self) -> bool {
+        match self.mode {
+            #[cfg(feature = "compiler")]
+            ChunkMode::Text => false,
+            ChunkMode::Binary => true,
+            ChunkMode::Auto => {
+                cfg!(not(feature = "compiler"))
+                    || self
+                        .contents
+                        .first()
+                        .is_some_and(|b| b.is_ascii_control() && !b.is_ascii_whitespace())
+            }
+        }
+    }
+
+    /// Compiles the chunk if needed and pushes it onto the stack as a function
+    fn load(&self) -> Result<(), LuauError> {
+        #[cfg(feature = "compiler")]
+        let compiled;
+
+        let bytecode = if self.is_binary() {
+            self.contents.as_slice()
+        } else {
+            #[cfg(feature = "compiler")]
+            {
+                compiled = self.compiler.compile(&self.contents);
+
+                compiled
+                    .bytecode()
+                    .ok_or_else(|| LuauError::Syntax(compiled.error().unwrap_or_default().into()))?
+            }
+
+            #[cfg(not(feature = "compiler"))]
+            unreachable!("Source chunks require the compiler feature")
+        };
+
+        let Some(environment) = &self.environment else {
+            return self.luau.load(Some(&self.name), bytecode, 0);
+        };
+
+        self.luau.push(environment);
+        let result = self.luau.load(Some(&self.name), bytecode, -1);
+        // SAFETY: the environment is beneath the function, or at the top if loading failed
+        unsafe { lua_remove(self.luau.state, if result.is_ok() { -2 } else { -1 }) };
+
+        result
+    }
+
+    /// Loads the chunk as a function without running it
+    pub fn into_function(self) -> Result<LuauFunction, LuauError> {
+        self.load()?;
+
+        let function = self.luau.get::<LuauFunction>(-1);
+        self.luau.pop(1);
+
+        function.map_err(LuauError::Conversion)
+    }
+
+    /// Runs the chunk with `args`, accessible through `...`, and converts its results
+    pub fn call<A: IntoLuauMulti, R: FromLuauMulti>(self, args: A) -> Result<R, LuauError> {
+        let top = self.luau.top();
+
+        self.load()?;
+        let nargs = self.luau.push(args);
+
+        self.luau.call(nargs, LUA_MULTRET)?;
+
+        let results = R::from_luau_multi(self.luau, top + 1);
+        self.luau.pop(self.luau.top() - top);
+
+        results.map_err(LuauError::Conversion)
+    }
+
+    /// Runs the chunk and converts its results
+    pub fn eval<R: FromLuauMulti>(self) -> Result<R, LuauError> {
+        self.call(())
+    }
+
+    /// Runs the chunk and discards its results
+    pub fn exec(self) -> Result<(), LuauError> {
+        self.call(())
+    }
+}
+
+impl Luau {
+    /// Creates a builder for loading `contents`, which may be source or bytecode
+    pub fn chunk(&self, contents: impl AsRef<[u8]>) -> Chunk<'_> {
+        Chunk {
+            luau: self,
+            contents: contents.as_ref().to_vec(),
+            name: c"=chunk".to_owned(),
+            environment: None,
+            #[cfg(feature = "compiler")]
+            compiler: Compiler::new(),
+            mode: ChunkMode::Auto,
+        }
+    }
+
+    /// Compiles and runs `source`, discarding its results
+    #[cfg(feature = "compiler")]
+    pub fn exec(&self, source: impl AsRef<[u8]>) -> Result<(), LuauError> {
+        self.chunk(source).set_mode(ChunkMode::Text).exec()
+    }
+
+    /// Compiles and runs `source`, converting its results
+    #[cfg(feature = "compiler")]
+    pub fn eval<R: FromLuauMulti>(&self, source: impl AsRef<[u8]>) -> Result<R, LuauError> {
+        self.chunk(source).set_mode(ChunkMode::Text).eval()
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use crate::{compile::Compiler, ChunkMode, Luau, LuauError, LuauTable};
+
+    #[test]
+    fn exec_and_eval() {
+        let luau = Luau::default();
+
+        assert!(luau.exec("value = 1 + 2").is_ok());
+        assert_eq!(luau.eval::<f64>("return value").unwrap(), 3.0);
+        assert_eq!(
+            luau.eval::<(f64, String)>("return 1, 'a'").unwrap(),
+            (1.0, "a".to_string())
+        );
+        assert!(matches!(
+            luau.eval::<f64>("return 'a'"),
+            Err(LuauError::Conversion(_))
+        ));
+        assert!(matches!(luau.exec("return +"), Err(LuauError::Syntax(_))));
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn chunk_options() {
+        let luau = Luau::default();
+
+        luau.create_table();
+        let environment = luau.get::<LuauTable>(-1).unwrap();
+        luau.pop(1);
+
+        environment.set("x", 2.0).unwrap();
+
+        let result = luau
+            .chunk("local a = ... return a * x")
+            .set_name(c"=options")
+            .set_environment(environment)
+            .call::<_, f64>(21.0);
+
+        assert_eq!(result.unwrap(), 42.0);
+
+        let err = luau
+            .chunk("error('boom')")
+            .set_name(c"@script.luau")
+            .exec()
+            .unwrap_err();
+
+        assert!(
+            err.to_string().starts_with("script.luau:1: boom"),
+            "Expected the chunk name in the error, got {err}"
+        );
+
+        let bytecode = Compiler::new()
+            .compile("return 5")
+            .bytecode()
+            .unwrap()
+            .to_vec();
+
+        assert_eq!(luau.chunk(&bytecode).eval::<f64>().unwrap(), 5.0);
+        assert_eq!(
+            luau.chunk(&bytecode)
+                .set_mode(ChunkMode::Binary)
+                .eval::<f64>()
+                .unwrap(),
+            5.0
+        );
+        assert!(luau
+            .chunk(&bytecode)
+            .set_mode(ChunkMode::Text)
+            .exec()
+            .is_err());
+
+        let function = luau.chunk("return 6").into_function().unwrap();
+
+        assert_eq!(function.call::<_, f64>(()).unwrap(), 6.0);
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+}
@@ -347,7 +347,7 @@ mod tests {
 
         let load_result = luau.load(None, result.bytecode().unwrap(), 0);
 
-        assert_eq!(load_result, Ok(()));
+        assert!(load_result.is_ok(), "Expected bytecode to load");
     }
 
     #[test]
@@ -0,0 +1,605 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::c_int,
+    fmt::Display,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{ffi::prelude::*, Luau};
+
+/// Returns the name Luau uses for a type in error messages
+pub(crate) fn type_name(ty: LuauType) -> &'static str {
+    match ty {
+        LuauType::LUA_TNONE => "no value",
+        LuauType::LUA_TNIL => "nil",
+        LuauType::LUA_TBOOLEAN => "boolean",
+        LuauType::LUA_TLIGHTUSERDATA | LuauType::LUA_TUSERDATA => "userdata",
+        LuauType::LUA_TNUMBER => "number",
+        LuauType::LUA_TVECTOR => "vector",
+        LuauType::LUA_TSTRING => "string",
+        LuauType::LUA_TTABLE => "table",
+        LuauType::LUA_TFUNCTION => "function",
+        LuauType::LUA_TTHREAD => "thread",
+        LuauType::LUA_TBUFFER => "buffer",
+        LuauType::LUA_TPROTO | LuauType::LUA_TUPVAL | LuauType::LUA_TDEADKEY => "internal",
+    }
+}
+
+/// Error produced when a Luau value cannot be converted into a Rust value
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    /// The absolute stack index of the value that failed to convert
+    pub index: c_int,
+    /// The Luau type of the value at `index`
+    pub found: LuauType,
+    /// The name of the type that was expected
+    pub expected: &'static str,
+    /// Additional detail for when the type matched but the value could not be represented
+    pub reason: Option<String>,
+}
+
+impl ConversionError {
+    pub fn new(index: c_int, found: LuauType, expected: &'static str) -> Self {
+        Self {
+            index,
+            found,
+            expected,
+            reason: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot convert value at index {}, expected {} got {}",
+            self.index,
+            self.expected,
+            type_name(self.found)
+        )?;
+
+        if let Some(reason) = &self.reason {
+            write!(f, " ({reason})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ConversionError {}
+
+/// A value which can be pushed onto the Luau stack as exactly one Luau value
+pub trait IntoLuau {
+    /// Pushes the value onto the top of the stack
+    fn push_to(self, luau: &Luau);
+}
+
+/// A value which can be read from a single slot on the Luau stack
+pub trait FromLuau: Sized {
+    /// Reads the value at `idx`, which may refer to a slot above the top of the stack in which case the type is `LUA_TNONE`
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError>;
+}
+
+/// A value which can be pushed onto the Luau stack as any number of Luau values
+///
+/// Implemented for every [`IntoLuau`] type and tuples of them.
+pub trait IntoLuauMulti {
+    /// Pushes the values onto the top of the stack returning the amount of values pushed
+    fn push_multi(self, luau: &Luau) -> c_int;
+}
+
+/// A value which can be read from a number of consecutive slots on the Luau stack
+///
+/// Implemented for every [`FromLuau`] type and tuples of them.
+pub trait FromLuauMulti: Sized {
+    /// Reads the values starting at the absolute index `idx`
+    fn from_luau_multi(luau: &Luau, idx: c_int) -> Result<Self, ConversionError>;
+}
+
+impl<T: IntoLuau> IntoLuauMulti for T {
+    fn push_multi(self, luau: &Luau) -> c_int {
+        self.push_to(luau);
+        1
+    }
+}
+
+impl<T: FromLuau> FromLuauMulti for T {
+    fn from_luau_multi(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        T::from_luau(luau, idx)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: IntoLuau),*> IntoLuauMulti for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn push_multi(self, luau: &Luau) -> c_int {
+                let ($($name,)*) = self;
+                let mut count = 0;
+                $(
+                    $name.push_to(luau);
+                    count += 1;
+                )*
+                count
+            }
+        }
+
+        impl<$($name: FromLuau),*> FromLuauMulti for ($($name,)*) {
+            #[allow(unused_variables, unused_mut, unused_assignments, clippy::unused_unit)]
+            fn from_luau_multi(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+                let mut idx = idx;
+                Ok(($({
+                    let value = $name::from_luau(luau, idx)?;
+                    idx += 1;
+                    value
+                },)*))
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+impl_tuple!(A, B, C, D, E, F, G, H, I);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+impl IntoLuau for bool {
+    fn push_to(self, luau: &Luau) {
+        luau.push_boolean(self);
+    }
+}
+
+impl FromLuau for bool {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TBOOLEAN => Ok(luau.to_boolean(idx)),
+            found => Err(ConversionError::new(idx, found, "boolean")),
+        }
+    }
+}
+
+/// Reads a number without performing string coercion, as coercion would replace the value on the stack
+fn number_at(luau: &Luau, idx: c_int, expected: &'static str) -> Result<f64, ConversionError> {
+    let idx = luau.abs_index(idx);
+
+    match luau.type_of_or_none(idx) {
+        LuauType::LUA_TNUMBER => Ok(luau
+            .to_number(idx)
+            .expect("Expected number value to convert")),
+        found => Err(ConversionError::new(idx, found, expected)),
+    }
+}
+
+macro_rules! impl_float {
+    ($($ty:ty),*) => {
+        $(
+            impl IntoLuau for $ty {
+                fn push_to(self, luau: &Luau) {
+                    luau.push_number(self as f64);
+                }
+            }
+
+            impl FromLuau for $ty {
+                fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+                    number_at(luau, idx, "number").map(|n| n as $ty)
+                }
+            }
+        )*
+    };
+}
+
+impl_float!(f32, f64);
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl IntoLuau for $ty {
+                fn push_to(self, luau: &Luau) {
+                    luau.push_number(self as f64);
+                }
+            }
+
+            impl FromLuau for $ty {
+                fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+                    let idx = luau.abs_index(idx);
+                    let n = number_at(luau, idx, stringify!($ty))?;
+
+                    // the upper bound is exclusive as MAX + 1 is always exactly representable by a double
+                    if n.fract() == 0.0 && n >= <$ty>::MIN as f64 && n < <$ty>::MAX as f64 + 1.0 {
+                        Ok(n as $ty)
+                    } else {
+                        Err(ConversionError::new(idx, LuauType::LUA_TNUMBER, stringify!($ty))
+                            .with_reason(format!("{n} has no {} representation", stringify!($ty))))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoLuau for &str {
+    fn push_to(self, luau: &Luau) {
+        luau.push_string(self);
+    }
+}
+
+impl IntoLuau for String {
+    fn push_to(self, luau: &Luau) {
+        luau.push_string(self);
+    }
+}
+
+impl FromLuau for String {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TSTRING => luau
+                .to_str(idx)
+                .expect("Expected string value to convert")
+                .map(str::to_string)
+                .map_err(|err| {
+                    ConversionError::new(idx, LuauType::LUA_TSTRING, "string")
+                        .with_reason(err.to_string())
+                }),
+            found => Err(ConversionError::new(idx, found, "string")),
+        }
+    }
+}
+
+/// Pushes the bytes as a Luau string
+impl IntoLuau for &[u8] {
+    fn push_to(self, luau: &Luau) {
+        luau.push_string(self);
+    }
+}
+
+/// Pushes the bytes as a Luau string
+impl IntoLuau for Box<[u8]> {
+    fn push_to(self, luau: &Luau) {
+        luau.push_string(self);
+    }
+}
+
+/// Reads a Luau string as raw bytes without requiring it to be valid UTF-8
+impl FromLuau for Box<[u8]> {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TSTRING => Ok(luau
+                .to_str_slice(idx)
+                .expect("Expected string value to convert")
+                .into()),
+            found => Err(ConversionError::new(idx, found, "string")),
+        }
+    }
+}
+
+impl<T: IntoLuau> IntoLuau for Option<T> {
+    fn push_to(self, luau: &Luau) {
+        match self {
+            Some(value) => value.push_to(luau),
+            None => luau.push_nil(),
+        }
+    }
+}
+
+/// Converts `nil` and absent values to `None`
+impl<T: FromLuau> FromLuau for Option<T> {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TNONE | LuauType::LUA_TNIL => Ok(None),
+            _ => T::from_luau(luau, idx).map(Some),
+        }
+    }
+}
+
+/// Pushes the values as the array portion of a new table
+impl<T: IntoLuau> IntoLuau for Vec<T> {
+    fn push_to(self, luau: &Luau) {
+        luau.create_table_with_capacity(self.len().try_into().unwrap_or(0), 0);
+
+        for (i, value) in self.into_iter().enumerate() {
+            luau.push_number((i + 1) as f64);
+            value.push_to(luau);
+            luau.raw_set_table(-3);
+        }
+    }
+}
+
+/// Reads the array portion of a table, as sized by the length operator without invoking metamethods
+impl<T: FromLuau> FromLuau for Vec<T> {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+        let found = luau.type_of_or_none(idx);
+
+        if found != LuauType::LUA_TTABLE {
+            return Err(ConversionError::new(idx, found, "table"));
+        }
+
+        luau_stack_precondition!(luau.check_stack(1));
+
+        // SAFETY: idx is a valid table as checked above
+        let len = unsafe { lua_objlen(luau.to_ptr(), idx) };
+        let mut vec = Vec::with_capacity(len as usize);
+
+        for i in 1..=len {
+            // SAFETY: stack space is checked by the precondition
+            unsafe {
+                lua_rawgeti(luau.to_ptr(), idx, i);
+            }
+
+            let value = T::from_luau(luau, luau.top());
+            luau.pop(1);
+
+            vec.push(value.map_err(|err| {
+                ConversionError::new(idx, found, "table")
+                    .with_reason(format!("element {i}: {err}"))
+            })?);
+        }
+
+        Ok(vec)
+    }
+}
+
+impl<K: IntoLuau, V: IntoLuau, S> IntoLuau for HashMap<K, V, S> {
+    fn push_to(self, luau: &Luau) {
+        luau.create_table_with_capacity(0, self.len().try_into().unwrap_or(0));
+
+        for (key, value) in self {
+            key.push_to(luau);
+            value.push_to(luau);
+            luau.raw_set_table(-3);
+        }
+    }
+}
+
+/// Reads every key value pair of a table without invoking metamethods
+impl<K: FromLuau + Eq + Hash, V: FromLuau, S: BuildHasher + Default> FromLuau
+    for HashMap<K, V, S>
+{
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+        let found = luau.type_of_or_none(idx);
+
+        if found != LuauType::LUA_TTABLE {
+            return Err(ConversionError::new(idx, found, "table"));
+        }
+
+        luau_stack_precondition!(luau.check_stack(2));
+
+        let mut map = HashMap::default();
+
+        luau.push_nil();
+
+        // SAFETY: idx is a valid table and the stack has space for the key and value
+        while unsafe { lua_next(luau.to_ptr(), idx) } != 0 {
+            let top = luau.top();
+            let entry = K::from_luau(luau, top - 1).and_then(|key| {
+                V::from_luau(luau, top).map(|value| (key, value))
+            });
+
+            match entry {
+                Ok((key, value)) => {
+                    map.insert(key, value);
+                    // leave the key for the next iteration
+                    luau.pop(1);
+                }
+                Err(err) => {
+                    luau.pop(2);
+
+                    return Err(ConversionError::new(idx, found, "table")
+                        .with_reason(format!("entry: {err}")));
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// A native Luau vector value
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    #[cfg(feature = "luau_vector4")]
+    pub w: f32,
+}
+
+impl IntoLuau for Vector {
+    fn push_to(self, luau: &Luau) {
+        luau.push_vector(
+            self.x,
+            self.y,
+            self.z,
+            #[cfg(feature = "luau_vector4")]
+            self.w,
+        );
+    }
+}
+
+impl FromLuau for Vector {
+    #[cfg(not(feature = "luau_vector4"))]
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TVECTOR => {
+                let (x, y, z) = luau.to_vector(idx).expect("Expected vector value to convert");
+                Ok(Vector { x, y, z })
+            }
+            found => Err(ConversionError::new(idx, found, "vector")),
+        }
+    }
+
+    #[cfg(feature = "luau_vector4")]
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TVECTOR => {
+                let (x, y, z, w) = luau.to_vector(idx).expect("Expected vector value to convert");
+                Ok(Vector { x, y, z, w })
+            }
+            found => Err(ConversionError::new(idx, found, "vector")),
+        }
+    }
+}
+
+/// An owned copy of the contents of a Luau buffer
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Buffer(pub Vec<u8>);
+
+impl IntoLuau for Buffer {
+    fn push_to(self, luau: &Luau) {
+        luau_stack_precondition!(luau.check_stack(1));
+
+        let mut ptr: *mut u8 = std::ptr::null_mut();
+
+        luau.protect(0, 1, |state| ptr = unsafe { lua_newbuffer(state, self.0.len()).cast() });
+
+        // SAFETY: stack size is validated by the precondition and the buffer is allocated with the length of the data
+        unsafe { std::ptr::copy_nonoverlapping(self.0.as_ptr(), ptr, self.0.len()) };
+    }
+}
+
+impl FromLuau for Buffer {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let idx = luau.abs_index(idx);
+
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TBUFFER => {
+                let mut len = 0;
+                let ptr: *const u8 = luau.to_buffer_ptr(idx, &mut len).cast();
+
+                // SAFETY: Luau reports the correct length for a buffer
+                Ok(Buffer(unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec()))
+            }
+            found => Err(ConversionError::new(idx, found, "buffer")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{Luau, LuauType};
+
+    use super::{Buffer, ConversionError, FromLuau, Vector};
+
+    #[test]
+    fn primitives() {
+        let luau = Luau::default();
+
+        luau.push(true);
+        luau.push(12.5f64);
+        luau.push(42u8);
+        luau.push("hello");
+
+        assert_eq!(luau.get::<bool>(1), Ok(true));
+        assert_eq!(luau.get::<f64>(2), Ok(12.5));
+        assert_eq!(luau.get::<u8>(-2), Ok(42));
+        assert_eq!(luau.get::<String>(-1).as_deref(), Ok("hello"));
+
+        assert_eq!(
+            luau.get::<u8>(2),
+            Err(ConversionError::new(2, LuauType::LUA_TNUMBER, "u8")
+                .with_reason("12.5 has no u8 representation"))
+        );
+
+        assert_eq!(
+            luau.get::<f64>(4),
+            Err(ConversionError::new(4, LuauType::LUA_TSTRING, "number"))
+        );
+
+        // strings are not coerced as that would replace the value on the stack
+        assert!(luau.is_string(4));
+
+        // relative indices are reported as absolute indices
+        assert_eq!(
+            bool::from_luau(&luau, -1),
+            Err(ConversionError::new(4, LuauType::LUA_TSTRING, "boolean"))
+        );
+    }
+
+    #[test]
+    fn options_and_tuples() {
+        let luau = Luau::default();
+
+        assert_eq!(luau.push((1.0f64, None::<bool>, "a")), 3);
+        assert_eq!(luau.top(), 3);
+
+        assert_eq!(
+            luau.get::<(f64, Option<bool>, String, Option<f64>)>(1),
+            Ok((1.0, None, "a".to_string(), None))
+        );
+
+        assert_eq!(
+            luau.get::<(f64, f64)>(-3),
+            Err(ConversionError::new(2, LuauType::LUA_TNIL, "number"))
+        );
+    }
+
+    #[test]
+    fn collections() {
+        let luau = Luau::default();
+
+        luau.push(vec![1, 2, 3]);
+        assert_eq!(luau.get::<Vec<i32>>(-1), Ok(vec![1, 2, 3]));
+
+        let map = HashMap::from([("a".to_string(), 1.0), ("b".to_string(), 2.0)]);
+
+        luau.push(map.clone());
+        assert_eq!(luau.get::<HashMap<String, f64>>(-1), Ok(map));
+
+        assert!(luau
+            .get::<Vec<String>>(1)
+            .is_err_and(|err| err.index == 1 && err.reason.is_some()));
+
+        assert_eq!(luau.top(), 2);
+    }
+
+    #[test]
+    fn vectors_and_buffers() {
+        let luau = Luau::default();
+
+        let vector = Vector {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            #[cfg(feature = "luau_vector4")]
+            w: 4.0,
+        };
+
+        luau.push(vector);
+        luau.push(Buffer(vec![0xCA, 0xFE]));
+
+        assert_eq!(luau.get::<Vector>(1), Ok(vector));
+        assert_eq!(luau.get::<Buffer>(2), Ok(Buffer(vec![0xCA, 0xFE])));
+    }
+}
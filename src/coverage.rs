@@ -0,0 +1,428 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, c_int, c_void, CStr},
+    io::{self, Write},
+    slice,
+};
+
+use crate::{ffi::prelude::*, Luau};
+
+/// Hit counts of a single function, created by `Luau::coverage`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    /// Name of the function, None for the main chunk and anonymous functions
+    pub name: Option<String>,
+    /// Line the function was defined on
+    pub line_defined: c_int,
+    /// Nesting depth of the function, 0 for the main chunk
+    pub depth: c_int,
+    /// Number of times each line with code in this function was executed, excluding nested functions
+    pub hits: BTreeMap<c_int, u64>,
+}
+
+impl FunctionCoverage {
+    /// Name used in reports, anonymous functions are named after the line they were defined on
+    pub fn display_name(&self) -> String {
+        match (&self.name, self.depth) {
+            (Some(name), _) => name.clone(),
+            (None, 0) => "<main>".to_string(),
+            (None, _) => format!("<anonymous:{}>", self.line_defined),
+        }
+    }
+
+    /// Number of times the function was entered, approximated by the hits of its first line with code
+    pub fn calls(&self) -> u64 {
+        self.hits.values().next().copied().unwrap_or_default()
+    }
+
+    fn merge(&mut self, other: &FunctionCoverage) {
+        for (&line, &hits) in &other.hits {
+            *self.hits.entry(line).or_default() += hits;
+        }
+    }
+}
+
+/// Hit counts of the functions in a single source file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceCoverage {
+    /// Functions in the order they are defined
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl SourceCoverage {
+    /// Returns the hits of every line with code, lines shared by nested functions report the highest count
+    pub fn lines(&self) -> BTreeMap<c_int, u64> {
+        let mut lines = BTreeMap::new();
+
+        for function in &self.functions {
+            for (&line, &hits) in &function.hits {
+                let entry = lines.entry(line).or_default();
+                *entry = hits.max(*entry);
+            }
+        }
+
+        lines
+    }
+
+    fn merge(&mut self, other: &SourceCoverage) {
+        for function in &other.functions {
+            let existing = self.functions.iter_mut().find(|existing| {
+                existing.line_defined == function.line_defined
+                    && existing.depth == function.depth
+                    && existing.name == function.name
+            });
+
+            match existing {
+                Some(existing) => existing.merge(function),
+                None => self.functions.push(function.clone()),
+            }
+        }
+
+        self.functions
+            .sort_by_key(|function| (function.line_defined, function.depth));
+    }
+}
+
+/// Line and function hit counts gathered from functions compiled with coverage enabled
+///
+/// Reports from multiple runs can be combined with `merge` and written in lcov or Cobertura format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    sources: BTreeMap<String, SourceCoverage>,
+}
+
+/// Counts the lines which were hit and returns (covered, valid, rate)
+fn line_rate<'a>(hits: impl IntoIterator<Item = &'a u64>) -> (usize, usize, f64) {
+    let (covered, valid) = hits.into_iter().fold((0, 0), |(covered, valid), &hits| {
+        (covered + (hits > 0) as usize, valid + 1)
+    });
+
+    let rate = if valid == 0 {
+        1.0
+    } else {
+        covered as f64 / valid as f64
+    };
+
+    (covered, valid, rate)
+}
+
+/// Escapes text for use in an XML attribute
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+impl CoverageReport {
+    /// Creates an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the coverage of each source file by name
+    pub fn sources(&self) -> &BTreeMap<String, SourceCoverage> {
+        &self.sources
+    }
+
+    /// Adds the hit counts of `other` to this report
+    pub fn merge(&mut self, other: &CoverageReport) {
+        for (name, source) in &other.sources {
+            self.sources.entry(name.clone()).or_default().merge(source);
+        }
+    }
+
+    /// Writes the report as an lcov tracefile, as read by genhtml and most CI coverage services
+    pub fn write_lcov(&self, mut writer: impl Write) -> io::Result<()> {
+        for (name, source) in &self.sources {
+            writeln!(writer, "TN:")?;
+            writeln!(writer, "SF:{name}")?;
+
+            for function in &source.functions {
+                writeln!(
+                    writer,
+                    "FN:{},{}",
+                    function.line_defined,
+                    function.display_name()
+                )?;
+            }
+
+            for function in &source.functions {
+                writeln!(
+                    writer,
+                    "FNDA:{},{}",
+                    function.calls(),
+                    function.display_name()
+                )?;
+            }
+
+            let called = source
+                .functions
+                .iter()
+                .filter(|function| function.calls() > 0)
+                .count();
+
+            writeln!(writer, "FNF:{}", source.functions.len())?;
+            writeln!(writer, "FNH:{called}")?;
+
+            let lines = source.lines();
+
+            for (line, hits) in &lines {
+                writeln!(writer, "DA:{line},{hits}")?;
+            }
+
+            let (covered, valid, _) = line_rate(lines.values());
+
+            writeln!(writer, "LF:{valid}")?;
+            writeln!(writer, "LH:{covered}")?;
+            writeln!(writer, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the report as Cobertura XML, with one class per source file and one method per function
+    pub fn write_cobertura(&self, mut writer: impl Write) -> io::Result<()> {
+        let lines = self
+            .sources
+            .values()
+            .map(SourceCoverage::lines)
+            .collect::<Vec<_>>();
+
+        let (covered, valid, rate) = line_rate(lines.iter().flat_map(BTreeMap::values));
+
+        writeln!(writer, r#"<?xml version="1.0" ?>"#)?;
+        writeln!(
+            writer,
+            r#"<coverage line-rate="{rate:.4}" branch-rate="0" lines-covered="{covered}" lines-valid="{valid}" branches-covered="0" branches-valid="0" complexity="0" version="0" timestamp="0">"#
+        )?;
+        writeln!(writer, "  <sources>")?;
+        writeln!(writer, "    <source>.</source>")?;
+        writeln!(writer, "  </sources>")?;
+        writeln!(writer, "  <packages>")?;
+        writeln!(
+            writer,
+            r#"    <package name="luau" line-rate="{rate:.4}" branch-rate="0" complexity="0">"#
+        )?;
+        writeln!(writer, "      <classes>")?;
+
+        for (name, source) in &self.sources {
+            let name = escape_xml(name);
+            let lines = source.lines();
+            let (_, _, rate) = line_rate(lines.values());
+
+            writeln!(
+                writer,
+                r#"        <class name="{name}" filename="{name}" line-rate="{rate:.4}" branch-rate="0" complexity="0">"#
+            )?;
+            writeln!(writer, "          <methods>")?;
+
+            for function in &source.functions {
+                let (_, _, rate) = line_rate(function.hits.values());
+
+                writeln!(
+                    writer,
+                    r#"            <method name="{}" signature="" line-rate="{rate:.4}" branch-rate="0" complexity="0">"#,
+                    escape_xml(&function.display_name())
+                )?;
+                writeln!(writer, "              <lines>")?;
+
+                for (line, hits) in &function.hits {
+                    writeln!(
+                        writer,
+                        r#"                <line number="{line}" hits="{hits}"/>"#
+                    )?;
+                }
+
+                writeln!(writer, "              </lines>")?;
+                writeln!(writer, "            </method>")?;
+            }
+
+            writeln!(writer, "          </methods>")?;
+            writeln!(writer, "          <lines>")?;
+
+            for (line, hits) in &lines {
+                writeln!(
+                    writer,
+                    r#"            <line number="{line}" hits="{hits}"/>"#
+                )?;
+            }
+
+            writeln!(writer, "          </lines>")?;
+            writeln!(writer, "        </class>")?;
+        }
+
+        writeln!(writer, "      </classes>")?;
+        writeln!(writer, "    </package>")?;
+        writeln!(writer, "  </packages>")?;
+        writeln!(writer, "</coverage>")
+    }
+}
+
+unsafe extern "C-unwind" fn coverage_callback(
+    context: *mut c_void,
+    function: *const c_char,
+    line_defined: c_int,
+    depth: c_int,
+    hits: *const c_int,
+    size: usize,
+) {
+    let functions = &mut *(context as *mut Vec<FunctionCoverage>);
+
+    // hits are indexed by line, lines without code are negative
+    let hits = slice::from_raw_parts(hits, size)
+        .iter()
+        .enumerate()
+        .filter(|(_, &hits)| hits >= 0)
+        .map(|(line, &hits)| (line as c_int, hits as u64))
+        .collect();
+
+    functions.push(FunctionCoverage {
+        name: (!function.is_null())
+            .then(|| CStr::from_ptr(function).to_string_lossy().into_owned()),
+        line_defined,
+        depth,
+        hits,
+    });
+}
+
+impl Luau {
+    /// Gathers the hit counts of the function at `function_idx` and every function defined within it
+    ///
+    /// The function must have been compiled with a coverage level of at least 1, see `Compiler::set_coverage_level`.
+    /// The report names the source after the function's chunk name with any `@` or `=` prefix removed.
+    pub fn coverage(&self, function_idx: c_int) -> CoverageReport {
+        luau_stack_precondition!(self.check_index(function_idx));
+        luau_stack_precondition!(self.check_stack(1));
+        assert!(
+            self.is_function(function_idx),
+            "The value at function_idx must be a function"
+        );
+
+        let mut functions = Vec::<FunctionCoverage>::new();
+
+        // SAFETY: the function index is validated above and the callback only touches the functions vector
+        let source = unsafe {
+            lua_getcoverage(
+                self.state,
+                function_idx,
+                &raw mut functions as _,
+                coverage_callback,
+            );
+
+            let mut ar: LuaDebug = std::mem::zeroed();
+
+            lua_pushvalue(self.state, function_idx);
+            // negative levels read the function at that stack index instead of a call frame
+            lua_getinfo(self.state, -1, c"s".as_ptr(), &raw mut ar);
+            self.pop(1);
+
+            CStr::from_ptr(ar.source).to_string_lossy().into_owned()
+        };
+
+        let name = source
+            .strip_prefix(['@', '='])
+            .map_or(source.clone(), str::to_string);
+
+        let mut report = CoverageReport::new();
+        report.merge(&CoverageReport {
+            sources: BTreeMap::from([(name, SourceCoverage { functions })]),
+        });
+
+        report
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use crate::{compile::Compiler, Luau, LuauFunction};
+
+    const SOURCE: &str = "local function check(x)
+    if x > 0 then
+        return 'positive'
+    end
+    return 'negative'
+end
+check(1)
+return check";
+
+    fn load(luau: &Luau) -> LuauFunction {
+        luau.chunk(SOURCE)
+            .set_name(c"@scripts/check.luau")
+            .set_compiler(Compiler::new().set_coverage_level(1))
+            .into_function()
+            .unwrap()
+    }
+
+    #[test]
+    fn hits_and_merging() {
+        let luau = Luau::default();
+        let function = load(&luau);
+
+        let check = function.call::<_, LuauFunction>(()).unwrap();
+        check.call::<_, String>(1.0).unwrap();
+
+        luau.push(&function);
+        let first = luau.coverage(-1);
+        luau.pop(1);
+
+        let source = &first.sources()["scripts/check.luau"];
+        let lines = source.lines();
+
+        assert_eq!(source.functions.len(), 2);
+        assert_eq!(source.functions[1].name.as_deref(), Some("check"));
+        assert_eq!(source.functions[1].calls(), 2);
+        assert_eq!(lines[&3], 2);
+        assert_eq!(lines[&5], 0, "Expected the negative branch to be missed");
+
+        let mut merged = first.clone();
+        merged.merge(&first);
+
+        assert_eq!(merged.sources()["scripts/check.luau"].lines()[&3], 4);
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn report_formats() {
+        let luau = Luau::default();
+        let function = load(&luau);
+
+        function.call::<_, LuauFunction>(()).unwrap();
+
+        luau.push(&function);
+        let report = luau.coverage(-1);
+        luau.pop(1);
+
+        let mut lcov = Vec::new();
+        report.write_lcov(&mut lcov).unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+
+        assert!(lcov.starts_with("TN:\nSF:scripts/check.luau\n"));
+        assert!(lcov.contains("FN:1,check\n"));
+        assert!(lcov.contains("FNDA:1,check\n"));
+        assert!(lcov.contains("DA:3,1\n"));
+        assert!(lcov.contains("DA:5,0\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+
+        let mut cobertura = Vec::new();
+        report.write_cobertura(&mut cobertura).unwrap();
+        let cobertura = String::from_utf8(cobertura).unwrap();
+
+        assert!(
+            cobertura.contains(r#"<class name="scripts/check.luau" filename="scripts/check.luau""#)
+        );
+        assert!(cobertura.contains(r#"<method name="check""#));
+        assert!(cobertura.contains(r#"<line number="5" hits="0"/>"#));
+        assert!(cobertura.trim_end().ends_with("</coverage>"));
+    }
+}
@@ -0,0 +1,806 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::{c_int, CStr},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread::{self, JoinHandle},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    conversion::FromLuau, ffi::prelude::*, ConversionError, DebugAction, Luau, LuauFunction,
+    LuauTable, StopReason,
+};
+
+/// Luau threads are not distinguished, every stop is reported on this thread id
+const THREAD_ID: i64 = 1;
+
+/// The latest function loaded from each source file and the breakpoints requested for each file
+pub(crate) struct Sources {
+    root: PathBuf,
+    chunks: HashMap<PathBuf, LuauFunction>,
+    breakpoints: HashMap<PathBuf, Vec<c_int>>,
+}
+
+impl Sources {
+    /// Maps a chunk name such as `@path/to/file.luau` to a file relative to the source root
+    fn chunk_path(&self, chunk_name: &str) -> Option<PathBuf> {
+        let path = chunk_name.strip_prefix('@')?;
+
+        Some(normalize(&self.root.join(path)))
+    }
+}
+
+/// Removes `.` and `..` components without touching the file system, the file may not exist on this machine
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Records a function loaded by `Luau::load` so breakpoints can be set in it, the function must be at the top
+///
+/// A file which is loaded again replaces its previous function so stale chunks are not kept alive.
+pub(crate) fn chunk_loaded(luau: &Luau, chunk_name: &CStr) {
+    let Some(sources) = luau.get_associated().dap_sources.clone() else {
+        return;
+    };
+
+    let mut sources = sources.borrow_mut();
+
+    let Some(path) = sources.chunk_path(&chunk_name.to_string_lossy()) else {
+        return;
+    };
+
+    let function = luau
+        .get::<LuauFunction>(-1)
+        .expect("Loaded chunks should be functions");
+
+    let debugger = luau.debugger();
+    let lines = sources.breakpoints.get(&path).cloned().unwrap_or_default();
+
+    for &line in &lines {
+        debugger.set_breakpoint(&function, line);
+    }
+
+    // breakpoints in the replaced chunk could not be cleared once it is no longer tracked
+    if let Some(previous) = sources.chunks.insert(path, function) {
+        for &line in &lines {
+            debugger.clear_breakpoint(&previous, line);
+        }
+    }
+}
+
+/// Reads a message framed with a `Content-Length` header, returning None at the end of the stream
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+    })?;
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Writes a message framed with a `Content-Length` header
+fn write_message(writer: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// A value shown in the variables view
+struct Variable {
+    value: String,
+    type_name: String,
+    table: Option<LuauTable>,
+}
+
+impl FromLuau for Variable {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        let ty = luau.type_of_or_none(idx);
+
+        let value = match ty {
+            LuauType::LUA_TNONE => return Err(ConversionError::new(idx, ty, "value")),
+            LuauType::LUA_TNIL => "nil".to_string(),
+            LuauType::LUA_TBOOLEAN => luau.to_boolean(idx).to_string(),
+            LuauType::LUA_TNUMBER => luau.to_number(idx).unwrap_or_default().to_string(),
+            LuauType::LUA_TVECTOR => format!("{:?}", luau.to_vector(idx).unwrap_or_default()),
+            LuauType::LUA_TSTRING => format!(
+                "{:?}",
+                String::from_utf8_lossy(luau.to_str_slice(idx).unwrap_or_default())
+            ),
+            // SAFETY: the index was validated by type_of_or_none
+            _ => format!("{:p}", unsafe { lua_topointer(luau.state, idx) }),
+        };
+
+        // SAFETY: lua_typename returns a static string for every type
+        let type_name = unsafe { CStr::from_ptr(lua_typename(luau.state, ty)) }
+            .to_string_lossy()
+            .into_owned();
+
+        Ok(Variable {
+            value,
+            type_name,
+            table: LuauTable::from_luau(luau, idx).ok(),
+        })
+    }
+}
+
+/// Contents of a variables reference handed to the client while stopped
+#[derive(Clone)]
+enum Variables {
+    Locals(c_int),
+    Upvalues(c_int),
+    Table(LuauTable),
+}
+
+/// Connection state shared between the server and the debug handler
+struct Session {
+    requests: Receiver<Value>,
+    output: RefCell<Box<dyn Write>>,
+    seq: Cell<i64>,
+    sources: Rc<RefCell<Sources>>,
+    variables: RefCell<Vec<Variables>>,
+    configured: Cell<bool>,
+    connected: Cell<bool>,
+}
+
+impl Session {
+    fn send(&self, mut message: Value) {
+        if !self.connected.get() {
+            return;
+        }
+
+        self.seq.set(self.seq.get() + 1);
+        message["seq"] = self.seq.get().into();
+
+        if write_message(&mut *self.output.borrow_mut(), &message).is_err() {
+            self.connected.set(false);
+        }
+    }
+
+    fn respond(&self, request: &Value, body: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+
+        self.send(response);
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    /// Describes a chunk name as a DAP source
+    fn source(&self, chunk_name: &str) -> Value {
+        match self.sources.borrow().chunk_path(chunk_name) {
+            Some(path) => json!({
+                "name": path.file_name().map(|name| name.to_string_lossy()),
+                "path": path,
+            }),
+            None => json!({ "name": chunk_name.trim_start_matches('=') }),
+        }
+    }
+
+    fn reference(&self, variables: Variables) -> usize {
+        let mut references = self.variables.borrow_mut();
+        references.push(variables);
+
+        // references must be positive, 0 means the variable has no children
+        references.len()
+    }
+
+    fn variable(&self, name: String, variable: Variable) -> Value {
+        json!({
+            "name": name,
+            "value": variable.value,
+            "type": variable.type_name,
+            "variablesReference": variable.table.map_or(0, |table| self.reference(Variables::Table(table))),
+        })
+    }
+
+    fn set_breakpoints(&self, luau: &Luau, arguments: &Value) -> Result<Value, String> {
+        let mut sources = self.sources.borrow_mut();
+
+        let path = arguments["source"]["path"]
+            .as_str()
+            .ok_or("Breakpoints can only be set in sources with a path")?;
+        let path = normalize(&sources.root.join(path));
+
+        let lines = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_i64())
+            .map(|line| line as c_int)
+            .collect::<Vec<_>>();
+
+        let function = sources.chunks.get(&path).cloned();
+        let previous = sources.breakpoints.insert(path, lines.clone());
+        let debugger = luau.debugger();
+
+        if let Some(function) = &function {
+            for &line in previous.iter().flatten() {
+                debugger.clear_breakpoint(function, line);
+            }
+        }
+
+        let breakpoints = lines
+            .iter()
+            .map(|&line| {
+                let actual = function
+                    .as_ref()
+                    .and_then(|function| debugger.set_breakpoint(function, line));
+
+                json!({ "verified": actual.is_some(), "line": actual.unwrap_or(line) })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self, luau: &Luau) -> Value {
+        let frames = luau
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(level, frame)| {
+                let name = frame.name.unwrap_or_else(|| match frame.what.as_str() {
+                    "main" => "main chunk".to_string(),
+                    _ => "anonymous function".to_string(),
+                });
+
+                json!({
+                    "id": level,
+                    "name": name,
+                    "source": self.source(&frame.source),
+                    "line": frame.line.unwrap_or(0),
+                    "column": 0,
+                    "presentationHint": if frame.line.is_some() { "normal" } else { "subtle" },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, luau: &Luau, reference: usize) -> Result<Value, String> {
+        let contents = reference
+            .checked_sub(1)
+            .and_then(|index| self.variables.borrow().get(index).cloned())
+            .ok_or_else(|| format!("Unknown variables reference {reference}"))?;
+
+        let mut variables = Vec::new();
+
+        match contents {
+            Variables::Locals(level) => {
+                for n in 1.. {
+                    let Some((name, value)) = luau.get_local::<Variable>(level, n) else {
+                        break;
+                    };
+
+                    // internal locals such as for loop state are named in parentheses
+                    if let (false, Ok(value)) = (name.starts_with('('), value) {
+                        variables.push(self.variable(name, value));
+                    }
+                }
+            }
+            Variables::Upvalues(level) => {
+                for n in 1.. {
+                    let Some((name, value)) = luau.get_upvalue::<Variable>(level, n) else {
+                        break;
+                    };
+
+                    if let Ok(value) = value {
+                        variables.push(self.variable(name, value));
+                    }
+                }
+            }
+            Variables::Table(table) => {
+                luau.push(&table);
+
+                for (key, value) in luau
+                    .raw_pairs::<Variable, Variable>(-1)
+                    .filter_map(Result::ok)
+                {
+                    let name = match key.type_name.as_str() {
+                        "string" => key.value.trim_matches('"').to_string(),
+                        _ => format!("[{}]", key.value),
+                    };
+
+                    variables.push(self.variable(name, value));
+                }
+
+                luau.pop(1);
+            }
+        }
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Handles a request, returning how to resume if it was a request to resume while stopped
+    fn handle(&self, luau: &Luau, request: Value, stopped: bool) -> Option<DebugAction> {
+        let arguments = &request["arguments"];
+
+        let not_stopped = || Err("The program is not stopped".to_string());
+
+        let (body, action) = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => (
+                Ok(json!({ "supportsConfigurationDoneRequest": true })),
+                None,
+            ),
+            "launch" | "attach" => (Ok(Value::Null), None),
+            "configurationDone" => {
+                self.configured.set(true);
+                (Ok(Value::Null), None)
+            }
+            "setBreakpoints" => (self.set_breakpoints(luau, arguments), None),
+            "threads" => (
+                Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+                None,
+            ),
+            "stackTrace" if stopped => (Ok(self.stack_trace(luau)), None),
+            "scopes" if stopped => {
+                let level = arguments["frameId"].as_i64().unwrap_or_default() as c_int;
+
+                let scopes = json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": self.reference(Variables::Locals(level)), "expensive": false },
+                    { "name": "Upvalues", "variablesReference": self.reference(Variables::Upvalues(level)), "expensive": false },
+                ]});
+
+                (Ok(scopes), None)
+            }
+            "variables" if stopped => {
+                let reference =
+                    arguments["variablesReference"].as_u64().unwrap_or_default() as usize;
+
+                (self.variables(luau, reference), None)
+            }
+            "stackTrace" | "scopes" | "variables" => (not_stopped(), None),
+            "continue" => (
+                Ok(json!({ "allThreadsContinued": true })),
+                Some(DebugAction::Continue),
+            ),
+            "next" => (Ok(Value::Null), Some(DebugAction::StepOver)),
+            "stepIn" => (Ok(Value::Null), Some(DebugAction::StepIn)),
+            "stepOut" => (Ok(Value::Null), Some(DebugAction::StepOut)),
+            "disconnect" => {
+                self.respond(&request, Ok(Value::Null));
+                self.connected.set(false);
+
+                return stopped.then_some(DebugAction::Continue);
+            }
+            command => (Err(format!("Unsupported request '{command}'")), None),
+        };
+
+        self.respond(&request, body);
+
+        action.filter(|_| stopped)
+    }
+
+    /// Reports a stop and handles requests until the client resumes execution
+    fn stop(&self, luau: &Luau, reason: StopReason) -> DebugAction {
+        if !self.connected.get() {
+            return DebugAction::Continue;
+        }
+
+        let reason = match reason {
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        );
+
+        let action = loop {
+            let Ok(request) = self.requests.recv() else {
+                self.connected.set(false);
+                break DebugAction::Continue;
+            };
+
+            if let Some(action) = self.handle(luau, request, true) {
+                break action;
+            }
+        };
+
+        // references are only valid while stopped
+        self.variables.borrow_mut().clear();
+
+        action
+    }
+}
+
+/// Debug Adapter Protocol server attached to a state, allowing editors to set breakpoints and inspect variables
+///
+/// Requests are read on a background thread and handled on the thread running the state,
+/// either while stopped or when `poll` or `wait_for_configuration` are called.
+/// Chunks loaded with names starting with `@` are mapped to files relative to the source root.
+pub struct DapServer<'lua> {
+    luau: &'lua Luau,
+    session: Rc<Session>,
+    reader: Option<JoinHandle<()>>,
+    /// Unblocks the reader thread so it can be joined, None if the reader cannot be interrupted
+    shutdown: Option<Box<dyn FnOnce()>>,
+}
+
+impl<'lua> DapServer<'lua> {
+    /// Creates a server communicating with a client over `reader` and `writer`
+    ///
+    /// The thread reading requests is joined on drop once `reader` has reached the end of its input,
+    /// otherwise it exits when the next read returns.
+    pub fn new(
+        luau: &'lua Luau,
+        reader: impl Read + Send + 'static,
+        writer: impl Write + 'static,
+    ) -> Self {
+        Self::with_shutdown(luau, reader, writer, None)
+    }
+
+    fn with_shutdown(
+        luau: &'lua Luau,
+        reader: impl Read + Send + 'static,
+        writer: impl Write + 'static,
+        shutdown: Option<Box<dyn FnOnce()>>,
+    ) -> Self {
+        let (sender, requests) = mpsc::channel();
+
+        let reader = thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let sources = Rc::new(RefCell::new(Sources {
+            root: std::env::current_dir().unwrap_or_default(),
+            chunks: HashMap::new(),
+            breakpoints: HashMap::new(),
+        }));
+
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*luau.get_associated_mut()).dap_sources = Some(sources.clone()) };
+
+        let session = Rc::new(Session {
+            requests,
+            output: RefCell::new(Box::new(writer)),
+            seq: Cell::new(0),
+            sources,
+            variables: RefCell::new(Vec::new()),
+            configured: Cell::new(false),
+            connected: Cell::new(true),
+        });
+
+        let handler_session = session.clone();
+        luau.debugger()
+            .set_handler(move |luau, reason| handler_session.stop(luau, reason));
+
+        Self {
+            luau,
+            session,
+            reader: Some(reader),
+            shutdown,
+        }
+    }
+
+    /// Accepts a single client from `listener`
+    ///
+    /// The connection is shut down when the server is dropped.
+    pub fn accept(luau: &'lua Luau, listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        let connection = stream.try_clone()?;
+
+        Ok(Self::with_shutdown(
+            luau,
+            stream.try_clone()?,
+            stream,
+            Some(Box::new(move || {
+                let _ = connection.shutdown(Shutdown::Both);
+            })),
+        ))
+    }
+
+    /// Creates a server communicating over standard input and output, as editors do when launching an adapter
+    ///
+    /// Standard input cannot be interrupted so its reader thread is not joined if it is still waiting for input on drop.
+    pub fn stdio(luau: &'lua Luau) -> Self {
+        Self::new(luau, io::stdin(), io::stdout())
+    }
+
+    /// Sets the directory which chunk names are relative to, defaults to the current directory
+    pub fn set_source_root(&self, root: impl AsRef<Path>) {
+        self.session.sources.borrow_mut().root = normalize(root.as_ref());
+    }
+
+    /// Returns true until the client disconnects
+    pub fn is_connected(&self) -> bool {
+        self.session.connected.get()
+    }
+
+    /// Handles requests until the client finishes configuration, returning false if it disconnected first
+    ///
+    /// Clients set their initial breakpoints before finishing configuration so this should be called before running scripts.
+    pub fn wait_for_configuration(&self) -> bool {
+        while !self.session.configured.get() && self.session.connected.get() {
+            let Ok(request) = self.session.requests.recv() else {
+                self.session.connected.set(false);
+                break;
+            };
+
+            self.handle(request);
+        }
+
+        self.session.configured.get() && self.session.connected.get()
+    }
+
+    /// Handles pending requests without blocking
+    pub fn poll(&self) {
+        loop {
+            match self.session.requests.try_recv() {
+                Ok(request) => self.handle(request),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.session.connected.set(false);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn handle(&self, request: Value) {
+        let initialize = request["command"] == "initialize";
+
+        self.session.handle(self.luau, request, false);
+
+        if initialize {
+            self.session.event("initialized", Value::Null);
+        }
+    }
+}
+
+impl Drop for DapServer<'_> {
+    fn drop(&mut self) {
+        self.session.event("terminated", Value::Null);
+
+        let debugger = self.luau.debugger();
+        debugger.remove_handler();
+
+        let sources = self.session.sources.borrow();
+
+        for (path, function) in &sources.chunks {
+            for &line in sources.breakpoints.get(path).into_iter().flatten() {
+                debugger.clear_breakpoint(function, line);
+            }
+        }
+
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*self.luau.get_associated_mut()).dap_sources = None };
+
+        let interrupted = self.shutdown.take().map(|shutdown| shutdown()).is_some();
+
+        // a reader which cannot be interrupted, such as standard input, may block until the next message
+        if let Some(reader) = self.reader.take() {
+            if interrupted || reader.is_finished() {
+                let _ = reader.join();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use std::{
+        io::BufReader,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use serde_json::{json, Value};
+
+    use super::{read_message, write_message};
+    use crate::{compile::Compiler, DapServer, Luau};
+
+    const SOURCE: &str = "local function add(a, b)
+    local sum = a + b
+    return sum
+end
+return add(1, 2)";
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+        seq: i64,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) {
+            self.seq += 1;
+
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+
+            write_message(&mut self.writer, &request).unwrap();
+        }
+
+        /// Reads messages until one matches `kind`, which is an event name or the command of a response
+        fn expect(&mut self, kind: &str) -> Value {
+            loop {
+                let message = read_message(&mut self.reader)
+                    .unwrap()
+                    .expect("Expected the server to stay connected");
+
+                if message["event"] == kind || message["command"] == kind {
+                    return message;
+                }
+            }
+        }
+
+        fn call(&mut self, command: &str, arguments: Value) -> Value {
+            self.request(command, arguments);
+
+            let response = self.expect(command);
+            assert_eq!(response["success"], true, "{response}");
+
+            response["body"].clone()
+        }
+    }
+
+    #[test]
+    fn scripted_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+
+            let mut client = Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+                seq: 0,
+            };
+
+            client.call("initialize", json!({ "adapterID": "luau" }));
+            client.expect("initialized");
+
+            let breakpoints = client.call(
+                "setBreakpoints",
+                json!({
+                    "source": { "path": "/scripts/main.luau" },
+                    "breakpoints": [{ "line": 3 }],
+                }),
+            );
+
+            // the chunk has not been loaded yet
+            assert_eq!(breakpoints["breakpoints"][0]["verified"], false);
+
+            client.call("configurationDone", json!({}));
+
+            let stopped = client.expect("stopped");
+            assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+            let trace = client.call("stackTrace", json!({ "threadId": 1 }));
+            let top = &trace["stackFrames"][0];
+
+            assert_eq!(top["name"], "add");
+            assert_eq!(top["line"], 3);
+            assert_eq!(top["source"]["path"], "/scripts/main.luau");
+
+            let scopes = client.call("scopes", json!({ "frameId": top["id"] }));
+            let locals = client.call(
+                "variables",
+                json!({ "variablesReference": scopes["scopes"][0]["variablesReference"] }),
+            );
+
+            let locals = locals["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| (v["name"].as_str().unwrap(), v["value"].as_str().unwrap()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(locals, [("a", "1"), ("b", "2"), ("sum", "3")]);
+
+            client.call("continue", json!({ "threadId": 1 }));
+            client.expect("terminated");
+        });
+
+        let luau = Luau::default();
+        let server = DapServer::accept(&luau, &listener).unwrap();
+
+        server.set_source_root("/scripts");
+        assert!(server.wait_for_configuration());
+
+        let result = luau
+            .chunk(SOURCE)
+            .set_name(c"@main.luau")
+            .set_compiler(Compiler::new().set_debug_level(2))
+            .eval::<f64>();
+
+        assert_eq!(result.unwrap(), 3.0);
+
+        drop(server);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_on_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+
+            let mut client = Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+                seq: 0,
+            };
+
+            client.expect("terminated");
+
+            // the connection is closed by the server while the client keeps its end open
+            assert!(read_message(&mut client.reader).unwrap().is_none());
+        });
+
+        let luau = Luau::default();
+        let server = DapServer::accept(&luau, &listener).unwrap();
+
+        // joins the reader thread, which is blocked on the open connection
+        drop(server);
+        client.join().unwrap();
+    }
+}
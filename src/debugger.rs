@@ -0,0 +1,444 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_int, CStr},
+    fmt::Display,
+    rc::Rc,
+};
+
+use crate::{
+    conversion::{FromLuau, IntoLuau},
+    error::invoke_callback,
+    ffi::prelude::*,
+    ConversionError, Luau, LuauFunction,
+};
+
+pub(crate) type DebugHandler = Rc<RefCell<dyn FnMut(&Luau, StopReason) -> DebugAction>>;
+
+/// Why execution stopped and the debug handler was called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A breakpoint was reached
+    Breakpoint,
+    /// A step requested by the previous `DebugAction` finished
+    Step,
+}
+
+/// How execution continues after the debug handler returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint
+    Continue,
+    /// Stop at the next line, entering called functions
+    StepIn,
+    /// Stop at the next line of the current function or once it returns
+    StepOver,
+    /// Stop once the current function returns
+    StepOut,
+}
+
+/// A step in progress on a single thread
+#[derive(Clone, Copy)]
+pub(crate) struct StepState {
+    thread: *mut _LuaState,
+    action: DebugAction,
+    depth: c_int,
+    line: c_int,
+}
+
+/// A call frame read by `Luau::frame`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Name of the function, if it is known
+    pub name: Option<String>,
+    /// Chunk name of the function, such as `@path/to/file.luau` or `=[C]` for Rust and C functions
+    pub source: String,
+    /// Line being executed, None for Rust and C functions
+    pub line: Option<c_int>,
+    /// Kind of function, one of `Lua`, `C`, `main` or `tail`
+    pub what: String,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = self.source.strip_prefix(['@', '=']).unwrap_or(&self.source);
+
+        write!(f, "{source}")?;
+
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+
+        if let Some(name) = &self.name {
+            write!(f, " function {name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies a possibly null C string
+unsafe fn copy_str(ptr: *const std::ffi::c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+unsafe extern "C-unwind" fn debugbreak_callback(state: *mut _LuaState, ar: *mut LuaDebug) {
+    let line = (*ar).currentline;
+
+    invoke_callback(state, |luau| {
+        // a breakpoint reached while stepping ends the step
+        (*luau.get_associated_mut()).debug_step = None;
+
+        luau.stop(StopReason::Breakpoint, line);
+        0
+    });
+}
+
+unsafe extern "C-unwind" fn debugstep_callback(state: *mut _LuaState, ar: *mut LuaDebug) {
+    let luau = Luau::from_ptr(state);
+
+    let Some(step) = luau.get_associated().debug_step else {
+        return;
+    };
+
+    if step.thread != state {
+        return;
+    }
+
+    let depth = lua_stackdepth(state);
+    let line = (*ar).currentline;
+
+    let finished = match step.action {
+        DebugAction::Continue => true,
+        DebugAction::StepIn => depth != step.depth || line != step.line,
+        DebugAction::StepOver => depth < step.depth || (depth == step.depth && line != step.line),
+        DebugAction::StepOut => depth < step.depth,
+    };
+
+    if finished {
+        (*luau.get_associated_mut()).debug_step = None;
+        lua_singlestep(state, 0);
+
+        invoke_callback(state, |luau| {
+            luau.stop(StopReason::Step, line);
+            0
+        });
+    }
+}
+
+impl Luau {
+    /// Calls the debug handler and applies the action it returns to this thread
+    fn stop(&self, reason: StopReason, line: c_int) {
+        let Some(handler) = self.get_associated().debug_handler.clone() else {
+            return;
+        };
+
+        // breakpoints and steps reached by code which the handler runs are ignored
+        let Ok(mut handler) = handler.try_borrow_mut() else {
+            return;
+        };
+
+        let action = handler(self, reason);
+
+        if action == DebugAction::Continue {
+            return;
+        }
+
+        // SAFETY: the thread is paused in a debug callback and the associated data is only borrowed for the assignment
+        unsafe {
+            (*self.get_associated_mut()).debug_step = Some(StepState {
+                thread: self.state,
+                action,
+                depth: lua_stackdepth(self.state),
+                line,
+            });
+
+            lua_singlestep(self.state, 1);
+        }
+    }
+
+    /// Returns a debugger for setting breakpoints and handling stops in all threads of this state
+    pub fn debugger(&self) -> Debugger<'_> {
+        Debugger { luau: self }
+    }
+
+    /// Reads the call frame at `level`, where level 0 is the running function
+    ///
+    /// Inside a debug handler level 0 is the Luau function which stopped.
+    pub fn frame(&self, level: c_int) -> Option<Frame> {
+        // SAFETY: the strings are copied before the debug structure is dropped
+        unsafe {
+            let mut ar: LuaDebug = std::mem::zeroed();
+
+            if level < 0 || lua_getinfo(self.state, level, c"sln".as_ptr(), &raw mut ar) == 0 {
+                return None;
+            }
+
+            Some(Frame {
+                name: copy_str(ar.name),
+                source: copy_str(ar.source).unwrap_or_default(),
+                line: (ar.currentline >= 0).then_some(ar.currentline),
+                what: copy_str(ar.what).unwrap_or_default(),
+            })
+        }
+    }
+
+    /// Reads every call frame from the running function outwards
+    pub fn frames(&self) -> Vec<Frame> {
+        (0..).map_while(|level| self.frame(level)).collect()
+    }
+
+    /// Reads the name and value of local `n`, counting from 1, of the function at call `level`
+    ///
+    /// Returns None if the local does not exist or is not in scope, local names require compiler debug level 2.
+    pub fn get_local<T: FromLuau>(
+        &self,
+        level: c_int,
+        n: c_int,
+    ) -> Option<(String, Result<T, ConversionError>)> {
+        luau_stack_precondition!(self.check_stack(1));
+
+        // SAFETY: the stack size is checked by the precondition and the value is only pushed if the name is not null
+        let name = unsafe { copy_str(lua_getlocal(self.state, level, n))? };
+
+        let value = T::from_luau(self, -1);
+        self.pop(1);
+
+        Some((name, value))
+    }
+
+    /// Sets local `n` of the function at call `level` to `value`, returning its name if it exists
+    pub fn set_local<T: IntoLuau>(&self, level: c_int, n: c_int, value: T) -> Option<String> {
+        luau_stack_precondition!(self.check_stack(1));
+
+        value.push_to(self);
+
+        // SAFETY: lua_setlocal pops the value whether or not the local exists
+        unsafe { copy_str(lua_setlocal(self.state, level, n)) }
+    }
+
+    /// Pushes the function at call `level`, returning false if there is none
+    fn push_frame_function(&self, level: c_int) -> bool {
+        luau_stack_precondition!(self.check_stack(2));
+
+        // SAFETY: the stack size is checked by the precondition
+        unsafe {
+            let mut ar: LuaDebug = std::mem::zeroed();
+
+            level >= 0 && lua_getinfo(self.state, level, c"f".as_ptr(), &raw mut ar) != 0
+        }
+    }
+
+    /// Reads the name and value of upvalue `n`, counting from 1, of the function at call `level`
+    pub fn get_upvalue<T: FromLuau>(
+        &self,
+        level: c_int,
+        n: c_int,
+    ) -> Option<(String, Result<T, ConversionError>)> {
+        if !self.push_frame_function(level) {
+            return None;
+        }
+
+        // SAFETY: the function was pushed above and the value is only pushed if the name is not null
+        let Some(name) = (unsafe { copy_str(lua_getupvalue(self.state, -1, n)) }) else {
+            self.pop(1);
+            return None;
+        };
+
+        let value = T::from_luau(self, -1);
+        self.pop(2);
+
+        Some((name, value))
+    }
+
+    /// Sets upvalue `n` of the function at call `level` to `value`, returning its name if it exists
+    pub fn set_upvalue<T: IntoLuau>(&self, level: c_int, n: c_int, value: T) -> Option<String> {
+        if !self.push_frame_function(level) {
+            return None;
+        }
+
+        value.push_to(self);
+
+        // SAFETY: the function is beneath the value, which is only popped if the upvalue exists
+        let name = unsafe { copy_str(lua_setupvalue(self.state, -2, n)) };
+        self.pop(if name.is_some() { 1 } else { 2 });
+
+        name
+    }
+}
+
+/// Sets breakpoints and the handler called when threads of a state stop, created by `Luau::debugger`
+pub struct Debugger<'lua> {
+    luau: &'lua Luau,
+}
+
+impl Debugger<'_> {
+    /// Sets the handler called when a thread reaches a breakpoint or finishes a step
+    ///
+    /// The handler may inspect the stopped thread through `Luau::frame`, `Luau::get_local` and similar functions,
+    /// a panic inside the handler is raised as a Luau error in the stopped thread like one inside a Rust function.
+    pub fn set_handler(&self, handler: impl FnMut(&Luau, StopReason) -> DebugAction + 'static) {
+        // SAFETY: the state is valid and the associated data is only borrowed for the assignment
+        unsafe {
+            (*self.luau.get_associated_mut()).debug_handler = Some(Rc::new(RefCell::new(handler)));
+
+            let callbacks = lua_callbacks(self.luau.state);
+            (*callbacks).debugbreak = Some(debugbreak_callback);
+            (*callbacks).debugstep = Some(debugstep_callback);
+        }
+    }
+
+    /// Removes the handler, breakpoints are left in place but no longer stop execution
+    pub fn remove_handler(&self) {
+        // SAFETY: as above
+        unsafe {
+            let associated = self.luau.get_associated_mut();
+            (*associated).debug_handler = None;
+            (*associated).debug_step = None;
+
+            let callbacks = lua_callbacks(self.luau.state);
+            (*callbacks).debugbreak = None;
+            (*callbacks).debugstep = None;
+        }
+    }
+
+    fn breakpoint(&self, function: &LuauFunction, line: c_int, enabled: bool) -> Option<c_int> {
+        luau_stack_precondition!(self.luau.check_stack(1));
+
+        self.luau.push(function);
+
+        // SAFETY: the function was pushed above, Luau ignores functions which are not Luau functions
+        let line = unsafe { lua_breakpoint(self.luau.state, -1, line, enabled as c_int) };
+        self.luau.pop(1);
+
+        (line >= 0).then_some(line)
+    }
+
+    /// Sets a breakpoint on `line` of `function` or the functions defined within it
+    ///
+    /// Returns the line the breakpoint was placed on, which is the next line with code, or None if there is none.
+    pub fn set_breakpoint(&self, function: &LuauFunction, line: c_int) -> Option<c_int> {
+        self.breakpoint(function, line, true)
+    }
+
+    /// Clears a breakpoint set by `set_breakpoint`, returning the line it was cleared from
+    pub fn clear_breakpoint(&self, function: &LuauFunction, line: c_int) -> Option<c_int> {
+        self.breakpoint(function, line, false)
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use std::{
+        cell::RefCell,
+        panic::{catch_unwind, AssertUnwindSafe},
+        rc::Rc,
+    };
+
+    use crate::{compile::Compiler, DebugAction, Luau, LuauFunction, StopReason};
+
+    const SOURCE: &str = "local offset = 0
+local function add(a, b)
+    local sum = a + b + offset
+    return sum
+end
+local x = 1
+local y = add(x, 2)
+return y";
+
+    fn function(luau: &Luau) -> LuauFunction {
+        luau.chunk(SOURCE)
+            .set_name(c"@test.luau")
+            .set_compiler(Compiler::new().set_debug_level(2))
+            .into_function()
+            .unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let luau = Luau::default();
+        let function = function(&luau);
+        let debugger = luau.debugger();
+
+        let stops = Rc::new(RefCell::new(Vec::new()));
+        let recorded = stops.clone();
+
+        debugger.set_handler(move |luau, reason| {
+            let frame = luau.frame(0).unwrap();
+            recorded
+                .borrow_mut()
+                .push((reason, frame.name.clone(), frame.line));
+
+            match recorded.borrow().len() {
+                1 => {
+                    assert_eq!(frame.source, "@test.luau");
+                    assert_eq!(luau.get_local::<f64>(0, 3).unwrap().1, Ok(1.0));
+                    DebugAction::StepIn
+                }
+                2 => {
+                    assert_eq!(
+                        luau.get_local::<f64>(0, 2),
+                        Some(("b".to_string(), Ok(2.0)))
+                    );
+                    assert_eq!(luau.set_local(0, 2, 10.0).as_deref(), Some("b"));
+                    assert_eq!(luau.set_upvalue(0, 1, 100.0).as_deref(), Some("offset"));
+                    assert_eq!(luau.frame(1).unwrap().line, Some(7));
+                    DebugAction::StepOut
+                }
+                _ => DebugAction::Continue,
+            }
+        });
+
+        assert_eq!(debugger.set_breakpoint(&function, 7), Some(7));
+        assert_eq!(function.call::<_, f64>(()).unwrap(), 111.0);
+
+        let stops = stops.take();
+        assert_eq!(stops[0], (StopReason::Breakpoint, None, Some(7)));
+        assert_eq!(
+            stops[1],
+            (StopReason::Step, Some("add".to_string()), Some(3))
+        );
+        assert_eq!(stops[2].0, StopReason::Step);
+        assert_eq!(stops.len(), 3);
+
+        assert_eq!(debugger.clear_breakpoint(&function, 7), Some(7));
+        assert_eq!(function.call::<_, f64>(()).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn step_over() {
+        let luau = Luau::default();
+        let function = function(&luau);
+        let debugger = luau.debugger();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let recorded = lines.clone();
+
+        debugger.set_handler(move |luau, _| {
+            recorded.borrow_mut().push(luau.frame(0).unwrap().line);
+            DebugAction::StepOver
+        });
+
+        debugger.set_breakpoint(&function, 6);
+        assert_eq!(function.call::<_, f64>(()).unwrap(), 3.0);
+        assert_eq!(lines.take()[..3], [Some(6), Some(7), Some(8)]);
+    }
+
+    #[test]
+    fn handler_panic() {
+        let luau = Luau::default();
+        let function = function(&luau);
+        let debugger = luau.debugger();
+
+        debugger.set_handler(|_, _| panic!("handler failed"));
+        debugger.set_breakpoint(&function, 7);
+
+        let panic = catch_unwind(AssertUnwindSafe(|| function.call::<_, f64>(())))
+            .expect_err("Expected the panic to be resumed by the caller");
+
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"handler failed"));
+
+        // the state is still usable once the breakpoint is removed
+        debugger.clear_breakpoint(&function, 7);
+        assert_eq!(function.call::<_, f64>(()).unwrap(), 3.0);
+    }
+}
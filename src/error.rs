@@ -0,0 +1,475 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    convert::Infallible,
+    error::Error,
+    ffi::{c_int, CString},
+    fmt::Display,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+};
+
+use crate::{
+    conversion::type_name, ffi::prelude::*, ConversionError, Frame, Luau, MetaMethod, UserData,
+    UserDataRegistry,
+};
+
+thread_local! {
+    /// State and call depth of the innermost Rust function called through a crate trampoline
+    ///
+    /// Cleared while a protected call runs as errors inside it are caught by Luau instead.
+    static CALLBACK_FRAME: Cell<Option<(*mut _LuaState, c_int)>> = const { Cell::new(None) };
+}
+
+/// Restores the previous callback frame when dropped
+struct FrameGuard(Option<(*mut _LuaState, c_int)>);
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        CALLBACK_FRAME.set(self.0);
+    }
+}
+
+/// Panic payload which unwinds a Rust function to its trampoline, where the error is thrown to Luau
+pub(crate) enum RaisedError {
+    /// The error value is on the top of the stack
+    Value,
+    /// A message prefixed with the location of the calling Luau code, thrown with `luaL_errorL`
+    Message(CString),
+    /// A type error for an argument, thrown with `luaL_typeerrorL`
+    TypeError(c_int, CString),
+    /// An argument error with an extra message, thrown with `luaL_argerrorL`
+    ArgError(c_int, CString),
+}
+
+impl RaisedError {
+    /// Throws the error from the running C function
+    ///
+    /// # Safety
+    /// Must be called by a C function invoked by Luau on `state`, outside of `catch_unwind`
+    unsafe fn throw(self, state: *mut _LuaState) -> ! {
+        match self {
+            RaisedError::Value => lua_error(state),
+            RaisedError::Message(message) => luaL_errorL(state, c"%s".as_ptr(), message.as_ptr()),
+            RaisedError::TypeError(narg, expected) => {
+                luaL_typeerrorL(state, narg, expected.as_ptr())
+            }
+            RaisedError::ArgError(narg, extra) => luaL_argerrorL(state, narg, extra.as_ptr()),
+        }
+    }
+}
+
+/// Call stack captured when an error was raised, before the stack unwound
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Traceback {
+    /// Frames from the function which raised the error outwards
+    pub frames: Vec<Frame>,
+}
+
+impl Display for Traceback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{frame}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error produced by calling, loading or resuming Luau code
+#[derive(Debug)]
+pub enum LuauError {
+    /// A runtime error raised by Luau code
+    Runtime {
+        message: String,
+        /// Stack traceback of the thread which raised the error, if it could be captured
+        traceback: Option<Traceback>,
+    },
+    /// A memory allocation failed
+    Memory,
+    /// An error was raised while running the error handler
+    ErrorHandler(String),
+    /// Bytecode failed to load, the message is produced by the compiler or loader
+    Syntax(String),
+    /// An error returned from a Rust callback, such as a function created by `Luau::create_function`
+    External(Box<dyn Error>),
+    /// A value returned from Luau could not be converted into the requested Rust type
+    Conversion(ConversionError),
+}
+
+impl Display for LuauError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LuauError::Runtime { message, traceback } => {
+                write!(f, "{message}")?;
+
+                if let Some(traceback) = traceback {
+                    write!(f, "\nstack traceback:\n{traceback}")?;
+                }
+
+                Ok(())
+            }
+            LuauError::Memory => write!(f, "not enough memory"),
+            LuauError::ErrorHandler(message) => write!(f, "error in error handling: {message}"),
+            LuauError::Syntax(message) => write!(f, "{message}"),
+            LuauError::External(err) => write!(f, "{err}"),
+            LuauError::Conversion(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for LuauError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LuauError::External(err) => Some(err.as_ref()),
+            LuauError::Conversion(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl LuauError {
+    /// Returns a reference to the Rust error carried by an `External` error if it is of type `T`
+    pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
+        match self {
+            LuauError::External(err) => err.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Takes the Rust error carried by an `External` error if it is of type `T`, otherwise returns the error unchanged
+    pub fn downcast<T: Error + 'static>(self) -> Result<Box<T>, Self> {
+        match self {
+            LuauError::External(err) => err.downcast().map_err(LuauError::External),
+            err => Err(err),
+        }
+    }
+}
+
+impl From<ConversionError> for LuauError {
+    fn from(err: ConversionError) -> Self {
+        LuauError::Conversion(err)
+    }
+}
+
+/// Userdata which carries a Rust error through Luau as an error value
+pub(crate) struct ExternalError {
+    message: String,
+    error: Option<Box<dyn Error>>,
+}
+
+impl ExternalError {
+    pub(crate) fn new(error: Box<dyn Error>) -> Self {
+        Self {
+            message: error.to_string(),
+            error: Some(error),
+        }
+    }
+}
+
+impl UserData for ExternalError {
+    fn register(registry: &mut UserDataRegistry<Self>) {
+        registry.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            Ok::<_, Infallible>(this.message.clone())
+        });
+    }
+}
+
+/// Userdata which carries a Rust panic through Luau as an error value
+pub(crate) struct PanicError {
+    message: String,
+    payload: Option<Box<dyn Any + Send>>,
+}
+
+impl PanicError {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => Some(message.to_string()),
+            None => payload.downcast_ref::<String>().cloned(),
+        };
+
+        Self {
+            message: match message {
+                Some(message) => format!("Rust function panicked: {message}"),
+                None => "Rust function panicked".to_string(),
+            },
+            payload: Some(payload),
+        }
+    }
+}
+
+impl UserData for PanicError {
+    fn register(registry: &mut UserDataRegistry<Self>) {
+        registry.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            Ok::<_, Infallible>(this.message.clone())
+        });
+    }
+}
+
+/// Runs the body of a Rust function called from Luau, converting a panic into a Luau error
+///
+/// Luau errors are C++ exceptions which Rust cannot catch, so errors raised by the crate within `body`
+/// unwind as a Rust panic until they reach this frame where the error is thrown to Luau.
+/// Luau API calls made by `body` which may throw are run through `Luau::protect` for the same reason.
+///
+/// # Safety
+/// Must only be called from a C function invoked by Luau on `state`
+pub(crate) unsafe fn invoke_callback(
+    state: *mut _LuaState,
+    body: impl FnOnce(&Luau) -> c_int,
+) -> c_int {
+    let luau = Luau::from_ptr(state);
+    let depth = lua_stackdepth(state);
+
+    let result = {
+        let _frame = FrameGuard(CALLBACK_FRAME.replace(Some((state, depth))));
+
+        catch_unwind(AssertUnwindSafe(|| body(&luau)))
+    };
+
+    match result {
+        Ok(nresults) => nresults,
+        Err(payload) => match payload.downcast::<RaisedError>() {
+            Ok(raised) => raised.throw(state),
+            Err(payload) => {
+                luau_stack_precondition!(luau.check_stack(1));
+                luau.create_userdata(PanicError::new(payload));
+
+                lua_error(state)
+            }
+        },
+    }
+}
+
+/// Calls the operation passed by `Luau::protect` as a light userdata beneath its arguments
+pub(crate) unsafe extern "C-unwind" fn protected_trampoline(state: *mut _LuaState) -> c_int {
+    let op = &mut *lua_tolightuserdata(state, 1).cast::<&mut dyn FnMut(*mut _LuaState)>();
+    lua_remove(state, 1);
+
+    op(state);
+
+    lua_gettop(state)
+}
+
+/// Message handler used by `Luau::call` which captures the traceback of an error before the stack unwinds
+///
+/// The error value is returned unchanged and the traceback is stored until the call takes it.
+pub(crate) unsafe extern "C-unwind" fn traceback_handler(state: *mut _LuaState) -> c_int {
+    let luau = Luau::from_ptr(state);
+
+    // capturing the traceback would allocate again, the error is left for `Luau::call` to report as a memory error
+    if luau.is_memory_error(1) {
+        return 1;
+    }
+
+    guard_destructor(|| {
+        // level 0 is the handler itself
+        let traceback = luau.traceback(1);
+
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*luau.get_associated_mut()).traceback = Some(traceback) };
+    });
+
+    1
+}
+
+/// Runs a call in protected mode, errors raised within it are caught by Luau rather than the running Rust function
+pub(crate) fn protected_call<R>(call: impl FnOnce() -> R) -> R {
+    let _frame = FrameGuard(CALLBACK_FRAME.take());
+
+    call()
+}
+
+/// Runs a userdata destructor, or another callback which cannot raise errors, discarding a panic
+pub(crate) fn guard_destructor(dtor: impl FnOnce()) {
+    let _ = catch_unwind(AssertUnwindSafe(dtor));
+}
+
+impl Luau {
+    /// Returns true if a Rust function called by a crate trampoline of this state is running
+    pub(crate) fn in_callback(&self) -> bool {
+        CALLBACK_FRAME.get().is_some_and(|(state, _)| {
+            // SAFETY: both states are alive while the callback is running
+            unsafe { lua_mainthread(state) == lua_mainthread(self.state) }
+        })
+    }
+
+    /// Runs `op` on the `nargs` values on the top of the stack, which it replaces with `nresults` values
+    ///
+    /// Within a Rust function called by a crate trampoline the operation is called in protected mode by a
+    /// preallocated trampoline, as an error thrown by Luau cannot unwind through the trampoline's `catch_unwind`.
+    /// The caught error is raised again from the Rust function.
+    pub(crate) fn protect(&self, nargs: c_int, nresults: c_int, op: impl FnOnce(*mut _LuaState)) {
+        if !self.in_callback() {
+            op(self.state);
+            return;
+        }
+
+        // a memory error is raised again as its message, see `is_memory_error`
+        if !matches!(self.try_protect(nargs, nresults, op), LuauStatus::LUA_OK) {
+            self.raise_error();
+        }
+    }
+
+    /// Calls `op` on the `nargs` values on the top of the stack in protected mode by the preallocated trampoline
+    ///
+    /// No message handler is installed, on failure the arguments are replaced by the error value.
+    pub(crate) fn try_protect(
+        &self,
+        nargs: c_int,
+        nresults: c_int,
+        op: impl FnOnce(*mut _LuaState),
+    ) -> LuauStatus {
+        luau_stack_precondition!(self.check_stack(2));
+
+        let mut op = Some(op);
+        let mut run = |state| {
+            if let Some(op) = op.take() {
+                op(state);
+            }
+        };
+        let mut run: &mut dyn FnMut(*mut _LuaState) = &mut run;
+
+        // SAFETY: the trampoline and the operation are inserted beneath its arguments, neither allocates
+        unsafe {
+            lua_getref(self.state, self.get_associated().protected_trampoline);
+            lua_insert(self.state, -(nargs + 1));
+            lua_pushlightuserdata(self.state, (&raw mut run).cast());
+            lua_insert(self.state, -(nargs + 1));
+
+            protected_call(|| lua_pcall(self.state, nargs + 1, nresults, 0))
+        }
+    }
+
+    /// Throws the value on the top of the stack as a Luau error
+    ///
+    /// Within a Rust function called by a crate trampoline the error unwinds to the trampoline before it is thrown.
+    pub(crate) fn raise_error(&self) -> ! {
+        self.throw(RaisedError::Value)
+    }
+
+    /// Throws an error, unwinding to the trampoline first if a Rust function of this state is running
+    pub(crate) fn throw(&self, raised: RaisedError) -> ! {
+        // SAFETY: the state is valid for the lifetime of the Luau struct
+        if self.unwinds_at(unsafe { lua_stackdepth(self.state) }) {
+            resume_unwind(Box::new(raised));
+        }
+
+        // SAFETY: errors outside of a Rust function are thrown by the caller's C function or reach the panic handler
+        unsafe { raised.throw(self.state) }
+    }
+
+    /// Raises a string error prefixed with the location of the calling Luau code like `luaL_error`
+    pub(crate) fn raise_message(&self, message: String) -> ! {
+        self.throw(RaisedError::Message(
+            CString::new(message).unwrap_or_default(),
+        ))
+    }
+
+    /// Returns true if an error raised by the function at call depth `current` must unwind to a Rust function
+    fn unwinds_at(&self, current: c_int) -> bool {
+        CALLBACK_FRAME
+            .get()
+            .is_some_and(|(state, depth)| state == self.state && current == depth)
+    }
+
+    /// Raises a Rust error as a Luau error
+    ///
+    /// Luau code sees a userdata which converts to the error's message with `tostring`.
+    /// If the error reaches the Rust caller of `call` it is returned as `LuauError::External` and can be downcast to `E`.
+    pub fn raise<E: Error + 'static>(&self, error: E) -> ! {
+        self.raise_external(Box::new(error))
+    }
+
+    /// Raises a boxed Rust error as a Luau error which is returned as `LuauError::External` from `call`
+    pub(crate) fn raise_external(&self, error: Box<dyn Error>) -> ! {
+        luau_stack_precondition!(self.check_stack(1));
+
+        self.create_userdata(ExternalError::new(error));
+        self.error();
+
+        unreachable!("Luau errors do not return")
+    }
+
+    /// Produces a readable message for an error value without invoking metamethods
+    fn error_message(&self, idx: c_int) -> String {
+        match self.type_of(idx) {
+            LuauType::LUA_TSTRING | LuauType::LUA_TNUMBER => {
+                String::from_utf8_lossy(self.to_str_slice(idx).unwrap_or_default()).into_owned()
+            }
+            ty => format!("(error object is a {} value)", type_name(ty)),
+        }
+    }
+
+    /// Returns true if the error value at `idx` was produced by a failed allocation
+    ///
+    /// Luau reports memory errors with a fixed message and they become runtime errors once they pass through a
+    /// message handler or are raised again by a Rust function, so the message is matched after an allocation failed.
+    pub(crate) fn is_memory_error(&self, idx: c_int) -> bool {
+        self.get_associated().allocation_failed
+            && self.is_string(idx)
+            && self.to_str_slice(idx) == Some(b"not enough memory")
+    }
+
+    /// Converts an error status into a `LuauError` by consuming the error value on the top of the stack
+    pub(crate) fn pop_error(&self, status: LuauStatus, traceback: Option<Traceback>) -> LuauError {
+        let error = match status {
+            _ if self.is_memory_error(-1) || matches!(status, LuauStatus::LUA_ERRMEM) => {
+                // SAFETY: the associated data is only borrowed for the assignment
+                unsafe { (*self.get_associated_mut()).allocation_failed = false };
+
+                LuauError::Memory
+            }
+            LuauStatus::LUA_ERRERR => LuauError::ErrorHandler(self.error_message(-1)),
+            _ => match self.try_borrow_userdata_mut::<PanicError>(-1) {
+                Some(Ok(mut panic)) => {
+                    if let Some(payload) = panic.payload.take() {
+                        drop(panic);
+                        self.pop(1);
+
+                        // the Rust caller has control again so the panic can continue unwinding
+                        resume_unwind(payload);
+                    }
+
+                    LuauError::Runtime {
+                        message: panic.message.clone(),
+                        traceback,
+                    }
+                }
+                _ => self.external_error(traceback),
+            },
+        };
+
+        self.pop(1);
+
+        error
+    }
+
+    /// Converts the error value on the top of the stack, taking the Rust error if it carries one
+    fn external_error(&self, traceback: Option<Traceback>) -> LuauError {
+        match self.try_borrow_userdata_mut::<ExternalError>(-1) {
+            Some(Ok(mut external)) => match external.error.take() {
+                Some(err) => LuauError::External(err),
+                // the error was already taken by a previous call, only the message remains
+                None => LuauError::Runtime {
+                    message: external.message.clone(),
+                    traceback,
+                },
+            },
+            _ => LuauError::Runtime {
+                message: self.error_message(-1),
+                traceback,
+            },
+        }
+    }
+
+    /// Captures the call stack of the state starting from call `level`
+    pub(crate) fn traceback(&self, level: c_int) -> Traceback {
+        Traceback {
+            frames: (level..).map_while(|level| self.frame(level)).collect(),
+        }
+    }
+}
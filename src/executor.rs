@@ -0,0 +1,569 @@
+use std::{
+    any::TypeId,
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    ffi::c_int,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::{
+    borrow_function,
+    conversion::{FromLuauMulti, IntoLuauMulti},
+    error::invoke_callback,
+    ffi::prelude::*,
+    userdata::{RustFunction, FN_TAG},
+    Luau, LuauError, LuauFunction, LuauRef, LuauThread,
+};
+
+/// Value yielded by async functions to tell the driving `ThreadFuture` that the thread is waiting on a future
+static PENDING: u8 = 0;
+
+fn pending_marker() -> *mut std::ffi::c_void {
+    &raw const PENDING as *mut _
+}
+
+type PendingFuture<Fut> = Pin<Box<Fut>>;
+
+/// Pushes the table of pending futures and the running thread, which is the key of its future
+///
+/// The table is weakly keyed so a future is collected along with a thread which never completes it.
+fn push_future_key(luau: &Luau) {
+    luau_stack_precondition!(luau.check_stack(2));
+
+    luau.get_reference(luau.get_associated().pending_futures);
+
+    // SAFETY: the stack size is checked by the precondition
+    unsafe { lua_pushthread(luau.state) };
+}
+
+/// Releases the future the running thread is waiting on, which is collected once it is no longer referenced
+fn release_future(luau: &Luau) {
+    push_future_key(luau);
+    luau.push_nil();
+    luau.raw_set_table(-3);
+    luau.pop(1);
+}
+
+/// Polls the future the running thread is waiting on
+///
+/// The future is looked up by thread rather than kept on the stack as yielding moves the base of the stack.
+fn poll_future<Fut, R, E>(luau: &Luau) -> c_int
+where
+    Fut: Future<Output = Result<R, E>> + 'static,
+    R: IntoLuauMulti,
+    E: Into<Box<dyn Error>>,
+{
+    let Some(waker) = luau.async_waker() else {
+        release_future(luau);
+        luau.raise_message(
+            "async functions must be called from a thread driven by a ThreadFuture".to_string(),
+        )
+    };
+
+    push_future_key(luau);
+    luau.raw_get_table(-2);
+
+    // SAFETY: the future was stored for this thread by `invoke_async` with the same type parameters
+    // and the table keeps it alive after it is popped
+    let future = unsafe { luau.get_userdata_unchecked::<PendingFuture<Fut>>(-1) }
+        .expect("Expected a pending future for the running thread");
+    luau.pop(2);
+
+    match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(Ok(results)) => {
+            release_future(luau);
+            luau.pop(luau.top());
+            results.push_multi(luau)
+        }
+        Poll::Ready(Err(err)) => {
+            release_future(luau);
+            luau.raise_external(err.into())
+        }
+        Poll::Pending => {
+            // SAFETY: the marker is a static address which is never dereferenced
+            unsafe { lua_pushlightuserdata(luau.state, pending_marker()) };
+            luau.yield_luau(1)
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn invoke_async<A, R, E, F, Fut>(state: *mut _LuaState) -> c_int
+where
+    A: FromLuauMulti,
+    R: IntoLuauMulti,
+    E: Into<Box<dyn Error>>,
+    F: FnMut(Luau, A) -> Fut,
+    Fut: Future<Output = Result<R, E>> + 'static,
+{
+    invoke_callback(state, |luau| {
+        // SAFETY: the state is valid for the duration of the call
+        if unsafe { lua_isyieldable(luau.state) } == 0 {
+            luau.raise_message(
+                "attempt to call an async function across a C-call boundary".to_string(),
+            );
+        }
+
+        let args = match A::from_luau_multi(luau, 1) {
+            Ok(args) => args,
+            Err(err) => luau.argument_error(&err),
+        };
+
+        // the closure is released before polling so the future may call the function again
+        let future = {
+            let mut func = borrow_function::<F>(luau);
+
+            // SAFETY: the handle refers to the calling thread which is only collected after the future stored for it
+            func(unsafe { Luau::from_ptr(luau.state) }, args)
+        };
+
+        luau.pop(luau.top());
+        luau.push_userdata::<PendingFuture<Fut>>(Box::pin(future));
+
+        push_future_key(luau);
+
+        // SAFETY: the future is beneath the table and key, Rust functions start with room for the copy
+        unsafe { lua_pushvalue(luau.state, -3) };
+        luau.raw_set_table(-3);
+        luau.pop(2);
+
+        poll_future::<Fut, R, E>(luau)
+    })
+}
+
+unsafe extern "C-unwind" fn continue_async<Fut, R, E>(
+    state: *mut _LuaState,
+    _status: c_int,
+) -> c_int
+where
+    Fut: Future<Output = Result<R, E>> + 'static,
+    R: IntoLuauMulti,
+    E: Into<Box<dyn Error>>,
+{
+    invoke_callback(state, |luau| {
+        // values passed to resume are not used by async functions
+        luau.pop(luau.top());
+
+        poll_future::<Fut, R, E>(luau)
+    })
+}
+
+impl Luau {
+    /// Returns the waker of the `ThreadFuture` driving this thread
+    fn async_waker(&self) -> Option<Waker> {
+        match &self.get_associated().async_poll {
+            Some((thread, waker)) if *thread == self.state => Some(waker.clone()),
+            _ => None,
+        }
+    }
+
+    /// Creates a function which returns the results of the future created by `func` and pushes it onto the stack
+    ///
+    /// The calling thread yields until the future completes, so the function must be called by a thread
+    /// driven by a `ThreadFuture`, such as those spawned on a `LuauExecutor`, and not across a C-call boundary.
+    /// The `Luau` handle passed to `func` refers to the calling thread and must not be used once the state is closed.
+    pub fn create_async_function<A, R, E, F, Fut>(&self, func: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(Luau, A) -> Fut + 'static,
+        Fut: Future<Output = Result<R, E>> + 'static,
+    {
+        luau_stack_precondition!(self.check_stack(2));
+
+        self.new_userdata(TypeId::of::<RustFunction>(), FN_TAG, func);
+
+        // SAFETY: the closure userdata is the only upvalue as expected by `borrow_function`
+        unsafe {
+            self.push_raw_function(
+                invoke_async::<A, R, E, F, Fut>,
+                None,
+                1,
+                Some(continue_async::<Fut, R, E>),
+            );
+        }
+    }
+
+    /// Creates a thread which calls `function` with `args` when the returned future is first polled
+    pub fn thread_future<A: IntoLuauMulti, R: FromLuauMulti>(
+        &self,
+        function: &LuauFunction,
+        args: A,
+    ) -> ThreadFuture<'_, R> {
+        let thread = self.new_thread();
+
+        // the thread is kept alive by the reference rather than the stack of this state
+        let thread_ref = LuauRef::new(self, -1);
+        self.pop(1);
+
+        let state = thread.get_state();
+        state.push(function);
+        let nargs = state.push(args);
+
+        ThreadFuture {
+            luau: self,
+            thread,
+            _thread_ref: thread_ref,
+            nargs: Some(nargs),
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Future which resumes a Luau thread each time it is polled until the thread returns
+///
+/// Threads which yield from an async function are resumed once its future wakes them,
+/// threads which yield in any other way are resumed on the next poll and the yielded values are discarded.
+pub struct ThreadFuture<'lua, R> {
+    luau: &'lua Luau,
+    thread: LuauThread<'lua>,
+    _thread_ref: LuauRef,
+    nargs: Option<c_int>,
+    done: bool,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R: FromLuauMulti> Future for ThreadFuture<'_, R> {
+    type Output = Result<R, LuauError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        assert!(!this.done, "ThreadFuture polled after completion");
+
+        let thread = this.thread.get_state();
+        let nargs = this.nargs.take().unwrap_or(0);
+
+        // SAFETY: the associated data is only borrowed for the swaps
+        let previous = unsafe {
+            (*this.luau.get_associated_mut())
+                .async_poll
+                .replace((thread.state, cx.waker().clone()))
+        };
+
+        let result = this.luau.resume(&this.thread, nargs);
+
+        // SAFETY: as above
+        unsafe { (*this.luau.get_associated_mut()).async_poll = previous };
+
+        match result {
+            Ok(LuauStatus::LUA_OK) => {
+                this.done = true;
+
+                let results = R::from_luau_multi(thread, 1);
+                thread.pop(thread.top());
+
+                Poll::Ready(results.map_err(LuauError::Conversion))
+            }
+            Ok(_) => {
+                let waiting = thread.top() > 0
+                    && thread.to_lightuserdata::<std::ffi::c_void>(-1) == Some(pending_marker());
+
+                thread.pop(thread.top());
+
+                if !waiting {
+                    cx.waker().wake_by_ref();
+                }
+
+                Poll::Pending
+            }
+            Err(err) => {
+                this.done = true;
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+/// Handle to the output of a task spawned on a `LuauExecutor`
+pub struct TaskHandle<T> {
+    output: Rc<RefCell<Option<T>>>,
+}
+
+impl<T> TaskHandle<T> {
+    /// Returns true once the task has completed
+    pub fn is_finished(&self) -> bool {
+        self.output.borrow().is_some()
+    }
+
+    /// Takes the output of the task if it has completed
+    pub fn take(&self) -> Option<T> {
+        self.output.borrow_mut().take()
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    woken: Arc<Mutex<VecDeque<usize>>>,
+    thread: Thread,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.lock().unwrap().push_back(self.id);
+        self.thread.unpark();
+    }
+}
+
+/// Single threaded executor which drives futures, such as Luau threads, without an async runtime
+///
+/// Wakers may be called from any thread and unpark the thread which created the executor.
+pub struct LuauExecutor<'lua> {
+    luau: &'lua Luau,
+    tasks: Vec<Option<Pin<Box<dyn Future<Output = ()> + 'lua>>>>,
+    woken: Arc<Mutex<VecDeque<usize>>>,
+    thread: Thread,
+}
+
+impl<'lua> LuauExecutor<'lua> {
+    pub fn new(luau: &'lua Luau) -> Self {
+        Self {
+            luau,
+            tasks: Vec::new(),
+            woken: Arc::default(),
+            thread: thread::current(),
+        }
+    }
+
+    /// Spawns a future which is first polled by the next call to `run_until_stalled` or `run`
+    pub fn spawn<T: 'lua>(&mut self, future: impl Future<Output = T> + 'lua) -> TaskHandle<T> {
+        let output = Rc::new(RefCell::new(None));
+        let task_output = output.clone();
+
+        let id = self.tasks.len();
+        self.tasks.push(Some(Box::pin(async move {
+            let result = future.await;
+            *task_output.borrow_mut() = Some(result);
+        })));
+        self.woken.lock().unwrap().push_back(id);
+
+        TaskHandle { output }
+    }
+
+    /// Spawns a new thread calling `function` with `args`, see `Luau::thread_future`
+    pub fn spawn_function<A: IntoLuauMulti, R: FromLuauMulti + 'lua>(
+        &mut self,
+        function: &LuauFunction,
+        args: A,
+    ) -> TaskHandle<Result<R, LuauError>> {
+        let future = self.luau.thread_future(function, args);
+
+        self.spawn(future)
+    }
+
+    /// Returns the number of tasks which have not completed
+    pub fn pending(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    /// Polls woken tasks until none are left to poll, returning the number of tasks which have not completed
+    pub fn run_until_stalled(&mut self) -> usize {
+        loop {
+            let next = self.woken.lock().unwrap().pop_front();
+            let Some(id) = next else {
+                break;
+            };
+
+            // tasks may be woken several times before they are polled or after they completed
+            let Some(task) = self.tasks[id].as_mut() else {
+                continue;
+            };
+
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                woken: self.woken.clone(),
+                thread: self.thread.clone(),
+            }));
+
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                self.tasks[id] = None;
+            }
+        }
+
+        self.pending()
+    }
+
+    /// Runs tasks until all of them have completed, parking the current thread while none are woken
+    pub fn run(&mut self) {
+        while self.run_until_stalled() > 0 {
+            if self.woken.lock().unwrap().is_empty() {
+                thread::park();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use std::{
+        cell::Cell,
+        future::Future,
+        pin::Pin,
+        rc::Rc,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{Luau, LuauError, LuauExecutor, LuauFunction, LuauLibs};
+
+    /// Completes on the second poll after waking itself
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    /// Completes after a background thread sleeps for the duration
+    struct Sleep(Arc<Mutex<Option<bool>>>, Duration);
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut done = self.0.lock().unwrap();
+
+            match *done {
+                Some(true) => Poll::Ready(()),
+                Some(false) => Poll::Pending,
+                None => {
+                    *done = Some(false);
+
+                    let (shared, duration, waker) = (self.0.clone(), self.1, cx.waker().clone());
+                    thread::spawn(move || {
+                        thread::sleep(duration);
+                        *shared.lock().unwrap() = Some(true);
+                        waker.wake();
+                    });
+
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    fn set_global_function(luau: &Luau, name: &str) {
+        let function = luau.get::<LuauFunction>(-1).unwrap();
+        luau.pop(1);
+
+        luau.set_global(name, function).unwrap();
+    }
+
+    fn function(luau: &Luau, source: &str) -> LuauFunction {
+        luau.chunk(source).into_function().unwrap()
+    }
+
+    #[test]
+    fn async_functions() {
+        let luau = Luau::default();
+        let polls = Rc::new(Cell::new(0));
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let counted = polls.clone();
+        luau.create_async_function(move |_, (a, b): (f64, f64)| {
+            counted.set(counted.get() + 1);
+
+            async move {
+                YieldOnce(false).await;
+                Ok::<_, LuauError>(a + b)
+            }
+        });
+        set_global_function(&luau, "add");
+
+        luau.create_async_function(|_, ms: u64| async move {
+            Sleep(Arc::default(), Duration::from_millis(ms)).await;
+            Ok::<_, LuauError>(ms)
+        });
+        set_global_function(&luau, "sleep");
+
+        let mut executor = LuauExecutor::new(&luau);
+
+        let sum = executor.spawn_function::<_, f64>(
+            &function(&luau, "local a = ... return add(a, 2) + add(3, 4)"),
+            1.0,
+        );
+        let slept = executor.spawn_function::<_, (u64, f64)>(
+            &function(&luau, "coroutine.yield(1) return sleep(5), add(1, 1)"),
+            (),
+        );
+
+        executor.run();
+
+        assert_eq!(sum.take().unwrap().unwrap(), 10.0);
+        assert_eq!(slept.take().unwrap().unwrap(), (5, 2.0));
+        assert_eq!(polls.get(), 3);
+        assert_eq!(executor.pending(), 0);
+    }
+
+    #[test]
+    fn errors() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.create_async_function(|_, fail: bool| async move {
+            YieldOnce(false).await;
+
+            match fail {
+                true => Err("failed"),
+                false => Ok(()),
+            }
+        });
+        set_global_function(&luau, "wait");
+
+        let mut executor = LuauExecutor::new(&luau);
+
+        let caught = executor.spawn_function::<_, (bool, String)>(
+            &function(
+                &luau,
+                "local ok, err = pcall(wait, true) return ok, tostring(err)",
+            ),
+            (),
+        );
+        let nested =
+            executor.spawn_function::<_, ()>(&function(&luau, "coroutine.wrap(wait)(false)"), ());
+
+        executor.run();
+
+        assert_eq!(
+            caught.take().unwrap().unwrap(),
+            (false, "failed".to_string())
+        );
+
+        let err = nested.take().unwrap().unwrap_err();
+        assert!(
+            err.to_string().contains("driven by a ThreadFuture"),
+            "Expected an error for an async function on an undriven thread, got {err}"
+        );
+
+        // calling outside of an executor raises an error instead of yielding
+        assert!(function(&luau, "wait(false)").call::<_, ()>(()).is_err());
+    }
+}
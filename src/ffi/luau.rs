@@ -43,6 +43,7 @@ pub enum LuauStatus {
 }
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(non_camel_case_types)]
 pub enum CoroutineStatus {
     /// The coroutine is running
@@ -65,6 +66,7 @@ pub struct _LuaState {
 }
 
 #[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Tag(pub c_int);
 
 /// Type for native C functions that can be passed to Luau.
@@ -83,7 +85,7 @@ pub type LuaDestructor = unsafe extern "C-unwind" fn(*mut _LuaState, *mut c_void
 
 /// Luau type value
 #[repr(C)]
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
 #[allow(non_camel_case_types)]
 pub enum LuauType {
     LUA_TNONE = -1,
@@ -375,6 +377,15 @@ extern "C-unwind" {
     /// Creates a new sized userdata with a specified tag
     pub fn lua_newuserdatatagged(state: *mut _LuaState, sz: usize, tag: Tag) -> *mut c_void;
 
+    /// Creates a new sized userdata with a specified tag and the metatable assigned to that tag
+    ///
+    /// The metatable is assigned with lua_setuserdatametatable, if there is none the userdata has no metatable
+    pub fn lua_newuserdatataggedwithmetatable(
+        state: *mut _LuaState,
+        sz: usize,
+        tag: Tag,
+    ) -> *mut c_void;
+
     /// Creates a new userdata object with a destructor callback which is invoked on GC
     pub fn lua_newuserdatadtor(state: *mut _LuaState, sz: usize, dtor: LuaDestructor);
 }
@@ -577,10 +588,10 @@ pub enum GCOperation {
     /// run a full GC cycle; not recommended for latency sensitive applications
     LUA_GCCOLLECT,
 
-    /// return the heap size in KB and the remainder in bytes
+    /// return the heap size in KB
     LUA_GCCOUNT,
 
-    /// return the heap size in KB and the remainder in bits
+    /// return the remainder of the heap size in bytes, see LUA_GCCOUNT
     LUA_GCCOUNTB,
 
     /// return 1 if GC is active (not stopped); note that GC may not be actively collecting even if it's running
@@ -630,7 +641,7 @@ extern "C-unwind" {
 
     /// Gets the total allocation size of the provided category.
     ///
-    /// Returns the total allocation size of all categories if the category provided is zero
+    /// Returns the total allocation size of all categories if the category provided is negative
     pub fn lua_totalbytes(state: *mut _LuaState, category: c_int) -> usize;
 }
 
@@ -679,7 +690,7 @@ extern "C-unwind" {
     ///
     /// This cannot be reassigned
     ///
-    /// This metatable can be retrieved lua_getuserdatametatable
+    /// This metatable can be retrieved lua_getuserdatametatable, the metatable at `idx` is popped
     pub fn lua_setuserdatametatable(state: *mut _LuaState, tag: Tag, idx: c_int);
 
     /// Get's a full userdata tag metatable
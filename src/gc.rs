@@ -0,0 +1,161 @@
+use std::{
+    ffi::c_int,
+    time::{Duration, Instant},
+};
+
+use crate::{ffi::prelude::*, Luau};
+
+impl Luau {
+    fn gc(&self, what: GCOperation, data: c_int) -> c_int {
+        // SAFETY: the state is valid and none of the operations raise errors
+        unsafe { lua_gc(self.state, what, data) }
+    }
+
+    /// Runs a full garbage collection cycle, not recommended for latency sensitive applications
+    pub fn gc_collect(&self) {
+        self.gc(GCOperation::LUA_GCCOLLECT, 0);
+    }
+
+    /// Performs an explicit incremental step of roughly `kb` kilobytes of work
+    ///
+    /// Returns true if the step finished a collection cycle.
+    pub fn gc_step(&self, kb: c_int) -> bool {
+        assert!(kb >= 0, "GC step size must not be negative");
+
+        self.gc(GCOperation::LUA_GCSTEP, kb) != 0
+    }
+
+    /// Performs incremental steps of `kb` kilobytes until `budget` has elapsed or a cycle is finished
+    ///
+    /// Intended to be called once per frame with automatic collection stopped by `gc_stop`,
+    /// returns true if a collection cycle was finished.
+    pub fn gc_step_for(&self, budget: Duration, kb: c_int) -> bool {
+        let start = Instant::now();
+
+        loop {
+            if self.gc_step(kb) {
+                return true;
+            }
+
+            if start.elapsed() >= budget {
+                return false;
+            }
+        }
+    }
+
+    /// Stops incremental garbage collection until `gc_restart` is called
+    pub fn gc_stop(&self) {
+        self.gc(GCOperation::LUA_GCSTOP, 0);
+    }
+
+    /// Restarts incremental garbage collection stopped by `gc_stop`
+    pub fn gc_restart(&self) {
+        self.gc(GCOperation::LUA_GCRESTART, 0);
+    }
+
+    /// Returns true if incremental garbage collection is not stopped, it may not be actively collecting
+    pub fn gc_is_running(&self) -> bool {
+        self.gc(GCOperation::LUA_GCISRUNNING, 0) != 0
+    }
+
+    /// Returns the size of the heap in bytes as seen by the garbage collector
+    pub fn gc_count_bytes(&self) -> usize {
+        let kb = self.gc(GCOperation::LUA_GCCOUNT, 0) as usize;
+        let remainder = self.gc(GCOperation::LUA_GCCOUNTB, 0) as usize;
+
+        (kb << 10) + remainder
+    }
+
+    /// Sets the heap size the collector aims for as a percentage of live data, returning the previous goal
+    ///
+    /// The goal must be above 100%, the default is 200%.
+    pub fn gc_set_goal(&self, percent: c_int) -> c_int {
+        assert!(percent > 100, "GC goal must be above 100%");
+
+        self.gc(GCOperation::LUA_GCSETGOAL, percent)
+    }
+
+    /// Sets the pace of collection relative to allocation as a percentage, returning the previous multiplier
+    ///
+    /// The multiplier must be at least 150%, the default is 200%.
+    /// Refer to `GCOperation::LUA_GCSETGOAL` for values which suit a given goal.
+    pub fn gc_set_step_multiplier(&self, percent: c_int) -> c_int {
+        assert!(percent >= 150, "GC step multiplier must be at least 150%");
+
+        self.gc(GCOperation::LUA_GCSETSTEPMUL, percent)
+    }
+
+    /// Sets the kilobytes allocated between collector steps, returning the previous step size
+    pub fn gc_set_step_size(&self, kb: c_int) -> c_int {
+        assert!(
+            kb > 0 && kb <= c_int::MAX >> 10,
+            "GC step size must be positive and fit in bytes"
+        );
+
+        self.gc(GCOperation::LUA_GCSETSTEPSIZE, kb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::Luau;
+
+    #[test]
+    fn stop_and_restart() {
+        let luau = Luau::default();
+
+        assert!(luau.gc_is_running());
+
+        luau.gc_stop();
+        assert!(!luau.gc_is_running());
+
+        luau.gc_restart();
+        assert!(luau.gc_is_running());
+    }
+
+    #[test]
+    fn collect() {
+        let luau = Luau::default();
+
+        luau.gc_collect();
+        let baseline = luau.gc_count_bytes();
+
+        for _ in 0..100 {
+            luau.create_table_with_capacity(64, 0);
+        }
+
+        let peak = luau.gc_count_bytes();
+        assert!(peak > baseline);
+
+        luau.pop(100);
+        luau.gc_stop();
+
+        // a cycle eventually finishes while automatic collection is stopped
+        while !luau.gc_step_for(Duration::from_millis(1), 1) {}
+
+        luau.gc_collect();
+        assert!(luau.gc_count_bytes() < peak);
+    }
+
+    #[test]
+    fn parameters() {
+        let luau = Luau::default();
+
+        let goal = luau.gc_set_goal(150);
+        assert_eq!(luau.gc_set_goal(goal), 150);
+
+        let step_multiplier = luau.gc_set_step_multiplier(300);
+        assert_eq!(luau.gc_set_step_multiplier(step_multiplier), 300);
+
+        let step_size = luau.gc_set_step_size(4);
+        assert_eq!(luau.gc_set_step_size(step_size), 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_goal() {
+        Luau::default().gc_set_goal(100);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::c_int,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{guard_destructor, invoke_callback},
+    ffi::prelude::*,
+    Luau,
+};
+
+pub(crate) type InterruptCallback = Rc<RefCell<dyn FnMut(&Luau, bool) -> InterruptAction>>;
+
+/// Reason a script was stopped by an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptError {
+    /// The script ran past its deadline
+    Timeout,
+    /// The script reached more safepoints than its budget allowed
+    BudgetExhausted,
+    /// The script was stopped by a user provided interrupt
+    Interrupted,
+}
+
+impl Error for InterruptError {}
+
+impl Display for InterruptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterruptError::Timeout => write!(f, "script timed out"),
+            InterruptError::BudgetExhausted => write!(f, "script exhausted its execution budget"),
+            InterruptError::Interrupted => write!(f, "script was interrupted"),
+        }
+    }
+}
+
+/// Action taken after an interrupt callback returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptAction {
+    /// Keep running the script
+    Continue,
+    /// Raise the error in the running script where it can be caught by `pcall`
+    Error(InterruptError),
+    /// Stop the script, the error is raised again at every safepoint until it reaches the Rust caller of `call` or `resume`
+    Terminate(InterruptError),
+}
+
+pub(crate) unsafe extern "C-unwind" fn interrupt_callback(state: *mut _LuaState, gc: c_int) {
+    let luau = Luau::from_ptr(state);
+
+    // errors cannot be raised while the garbage collector is running
+    if gc >= 0 {
+        guard_destructor(|| {
+            luau.run_interrupt(true);
+        });
+
+        return;
+    }
+
+    if let Some(sampler) = luau.get_associated().profiler.clone() {
+        guard_destructor(|| sampler.sample(&luau));
+    }
+
+    invoke_callback(state, |luau| {
+        let action = match luau.get_associated().terminating {
+            Some(err) => InterruptAction::Terminate(err),
+            None => luau.run_interrupt(false),
+        };
+
+        match action {
+            InterruptAction::Continue => 0,
+            InterruptAction::Error(err) => luau.raise(err),
+            InterruptAction::Terminate(err) => {
+                (*luau.get_associated_mut()).terminating = Some(err);
+                luau.raise(err)
+            }
+        }
+    });
+}
+
+impl Luau {
+    fn run_interrupt(&self, gc: bool) -> InterruptAction {
+        let Some(interrupt) = self.get_associated().interrupt.clone() else {
+            return InterruptAction::Continue;
+        };
+
+        // safepoints reached by Luau code which the callback runs are ignored
+        let Ok(mut interrupt) = interrupt.try_borrow_mut() else {
+            return InterruptAction::Continue;
+        };
+
+        interrupt(self, gc)
+    }
+
+    /// Takes the error of a terminated script once control has returned to the outermost Rust caller
+    pub(crate) fn take_termination(&self) -> Option<InterruptError> {
+        if self.in_callback() {
+            return None;
+        }
+
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*self.get_associated_mut()).terminating.take() }
+    }
+
+    /// Sets a callback which is called at safepoints, such as loop back edges and function calls, and during garbage collection
+    ///
+    /// The second argument is true when called by the garbage collector, in which case the returned action is ignored.
+    /// Safepoints reached while the callback itself runs Luau code do not call it again.
+    pub fn set_interrupt(&self, interrupt: impl FnMut(&Luau, bool) -> InterruptAction + 'static) {
+        // SAFETY: the state is valid and the associated data is only borrowed for the assignment
+        unsafe {
+            (*self.get_associated_mut()).interrupt = Some(Rc::new(RefCell::new(interrupt)));
+            (*lua_callbacks(self.state)).interrupt = Some(interrupt_callback);
+        }
+    }
+
+    /// Removes the callback set by `set_interrupt`, a script which is being terminated is still stopped
+    pub fn remove_interrupt(&self) {
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*self.get_associated_mut()).interrupt = None };
+    }
+
+    /// Stops scripts which are still running after `deadline` with `action`, such as `InterruptAction::Error`
+    ///
+    /// The limit is reported once and replaces any callback set by `set_interrupt`.
+    pub fn set_deadline(&self, deadline: Instant, action: fn(InterruptError) -> InterruptAction) {
+        let mut reported = false;
+
+        self.set_interrupt(move |_, gc| {
+            if gc || reported || Instant::now() < deadline {
+                return InterruptAction::Continue;
+            }
+
+            reported = true;
+            action(InterruptError::Timeout)
+        });
+    }
+
+    /// Stops scripts which are still running after `timeout` from now, see `set_deadline`
+    pub fn set_timeout(&self, timeout: Duration, action: fn(InterruptError) -> InterruptAction) {
+        self.set_deadline(Instant::now() + timeout, action);
+    }
+
+    /// Stops scripts after they reach `safepoints` safepoints with `action`, approximating an instruction budget
+    ///
+    /// The limit is reported once and replaces any callback set by `set_interrupt`.
+    pub fn set_budget(&self, safepoints: u64, action: fn(InterruptError) -> InterruptAction) {
+        let mut remaining = safepoints;
+        let mut reported = false;
+
+        self.set_interrupt(move |_, gc| {
+            if gc || reported {
+                return InterruptAction::Continue;
+            }
+
+            if remaining > 0 {
+                remaining -= 1;
+                return InterruptAction::Continue;
+            }
+
+            reported = true;
+            action(InterruptError::BudgetExhausted)
+        });
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{compile::Compiler, InterruptAction, InterruptError, Luau, LuauLibs};
+
+    fn load(luau: &Luau, source: &str) {
+        let result = Compiler::new().compile(source);
+
+        luau.load(None, result.bytecode().unwrap(), 0).unwrap();
+    }
+
+    #[test]
+    fn catchable_budget() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.set_budget(1000, InterruptAction::Error);
+
+        load(
+            &luau,
+            "local ok, err = pcall(function() while true do end end) return ok, tostring(err)",
+        );
+
+        assert!(luau.call(0, 2).is_ok());
+        assert_eq!(
+            luau.get::<(bool, String)>(1),
+            Ok((false, InterruptError::BudgetExhausted.to_string()))
+        );
+    }
+
+    #[test]
+    fn terminate_timeout() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.set_timeout(Duration::from_millis(10), InterruptAction::Terminate);
+
+        load(
+            &luau,
+            "while true do pcall(function() while true do end end) end",
+        );
+
+        let err = luau.call(0, 0).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<InterruptError>(),
+            Some(&InterruptError::Timeout)
+        );
+
+        // a script which returns after catching the error is still stopped
+        luau.set_timeout(Duration::from_millis(10), InterruptAction::Terminate);
+        load(&luau, "pcall(function() while true do end end) return 1");
+
+        assert!(luau.call(0, 1).is_err());
+        assert_eq!(luau.top(), 0, "Expected the results to be popped");
+
+        // the state can run scripts again once the error has reached the caller
+        load(&luau, "return 1");
+
+        assert!(luau.call(0, 1).is_ok());
+    }
+}
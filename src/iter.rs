@@ -0,0 +1,275 @@
+use std::{ffi::c_int, marker::PhantomData};
+
+use crate::{conversion::FromLuau, ffi::prelude::*, Luau, LuauError};
+
+/// Converts the key and value at the top of the stack and pops them
+fn pop_pair<K: FromLuau, V: FromLuau>(luau: &Luau) -> Result<(K, V), LuauError> {
+    let top = luau.top();
+
+    let pair = K::from_luau(luau, top - 1).and_then(|k| Ok((k, V::from_luau(luau, top)?)));
+    luau.pop(2);
+
+    pair.map_err(LuauError::Conversion)
+}
+
+/// Iterator over the key value pairs of a table, created by `Luau::pairs`
+///
+/// The current key is kept in a stack slot above the top of the stack when the iterator was created,
+/// which is removed when it is dropped.
+pub struct Pairs<'a, K, V> {
+    luau: &'a Luau,
+    table: c_int,
+    base: c_int,
+    done: bool,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K: FromLuau, V: FromLuau> Iterator for Pairs<'_, K, V> {
+    type Item = Result<(K, V), LuauError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        assert_eq!(
+            self.luau.top(),
+            self.base + 1,
+            "The stack must be balanced between iterations of a table"
+        );
+
+        luau_stack_precondition!(self.luau.check_stack(3));
+
+        // SAFETY: the table and key slot are valid indices
+        unsafe {
+            lua_pushvalue(self.luau.state, self.table);
+            lua_pushvalue(self.luau.state, self.base + 1);
+        }
+
+        // lua_next raises an error if the table was mutated such that the key can no longer be found
+        let status = self.luau.try_protect(2, LUA_MULTRET, |state| unsafe {
+            if lua_next(state, -2) != 0 {
+                lua_remove(state, -3);
+            } else {
+                lua_pop(state, 1);
+            }
+        });
+
+        if !matches!(status, LuauStatus::LUA_OK) {
+            self.done = true;
+            return Some(Err(self.luau.pop_error(status, None)));
+        }
+
+        if self.luau.top() == self.base + 1 {
+            self.done = true;
+            return None;
+        }
+
+        // SAFETY: the key is beneath the value at the top of the stack
+        unsafe {
+            lua_pushvalue(self.luau.state, -2);
+            lua_replace(self.luau.state, self.base + 1);
+        }
+
+        Some(pop_pair(self.luau))
+    }
+}
+
+impl<K, V> Drop for Pairs<'_, K, V> {
+    fn drop(&mut self) {
+        // SAFETY: the slot is only removed once
+        unsafe { lua_remove(self.luau.state, self.base + 1) };
+    }
+}
+
+/// Iterator over the key value pairs of a table without invoking metamethods, created by `Luau::raw_pairs`
+pub struct RawPairs<'a, K, V> {
+    luau: &'a Luau,
+    table: c_int,
+    iter: c_int,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K: FromLuau, V: FromLuau> Iterator for RawPairs<'_, K, V> {
+    type Item = Result<(K, V), LuauError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iter < 0 {
+            return None;
+        }
+
+        luau_stack_precondition!(self.luau.check_stack(2));
+
+        // SAFETY: the table index was validated when the iterator was created
+        self.iter = unsafe { lua_rawiter(self.luau.state, self.table, self.iter) };
+
+        if self.iter < 0 {
+            return None;
+        }
+
+        Some(pop_pair(self.luau))
+    }
+}
+
+/// Iterator over the array part of a table, created by `Luau::ipairs`
+pub struct IPairs<'a, V> {
+    luau: &'a Luau,
+    table: c_int,
+    index: c_int,
+    _marker: PhantomData<V>,
+}
+
+impl<V: FromLuau> Iterator for IPairs<'_, V> {
+    type Item = Result<(c_int, V), LuauError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        luau_stack_precondition!(self.luau.check_stack(1));
+
+        // SAFETY: the table index was validated when the iterator was created
+        unsafe {
+            // the length is read every step as the table may change during iteration
+            if self.index < 1 || self.index > lua_objlen(self.luau.state, self.table) {
+                return None;
+            }
+
+            if lua_rawgeti(self.luau.state, self.table, self.index) == LuauType::LUA_TNIL {
+                self.luau.pop(1);
+                self.index = 0;
+                return None;
+            }
+        }
+
+        let value = V::from_luau(self.luau, self.luau.top());
+        self.luau.pop(1);
+
+        let index = self.index;
+        self.index += 1;
+
+        Some(value.map(|v| (index, v)).map_err(LuauError::Conversion))
+    }
+}
+
+impl Luau {
+    fn table_index(&self, idx: c_int) -> c_int {
+        luau_stack_precondition!(self.check_index(idx));
+        assert!(self.is_table(idx), "The value at idx must be a table");
+
+        self.abs_index(idx)
+    }
+
+    /// Iterates over the key value pairs of the table at `idx` using `lua_next`
+    ///
+    /// A stack slot is reserved while the iterator is alive and the stack must be balanced between steps.
+    /// Mutating the table while iterating may end the iteration with an error, assigning existing fields is allowed.
+    pub fn pairs<K: FromLuau, V: FromLuau>(&self, idx: c_int) -> Pairs<'_, K, V> {
+        let table = self.table_index(idx);
+        let base = self.top();
+
+        luau_stack_precondition!(self.check_stack(1));
+
+        // SAFETY: the stack size is checked by the precondition
+        unsafe { lua_pushnil(self.state) };
+
+        Pairs {
+            luau: self,
+            table,
+            base,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Iterates over the key value pairs of the table at `idx` using `lua_rawiter`
+    ///
+    /// Traversal uses a position in the table rather than the previous key so it will not error if the table is mutated.
+    /// Keys which are added during iteration may or may not be visited.
+    pub fn raw_pairs<K: FromLuau, V: FromLuau>(&self, idx: c_int) -> RawPairs<'_, K, V> {
+        RawPairs {
+            luau: self,
+            table: self.table_index(idx),
+            iter: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Iterates over the values of the table at `idx` from index 1 until the first nil value
+    pub fn ipairs<V: FromLuau>(&self, idx: c_int) -> IPairs<'_, V> {
+        IPairs {
+            luau: self,
+            table: self.table_index(idx),
+            index: 1,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{lua_rawseti, Luau, LuauError};
+
+    #[test]
+    fn pairs() {
+        let luau = Luau::default();
+
+        let map = HashMap::from([("a".to_string(), 1.0), ("b".to_string(), 2.0)]);
+        luau.push(map.clone());
+
+        let pairs = luau
+            .pairs::<String, f64>(-1)
+            .collect::<Result<HashMap<_, _>, _>>()
+            .unwrap();
+        let raw_pairs = luau
+            .raw_pairs::<String, f64>(-1)
+            .collect::<Result<HashMap<_, _>, _>>()
+            .unwrap();
+
+        assert_eq!(pairs, map);
+        assert_eq!(raw_pairs, map);
+        assert_eq!(luau.top(), 1, "Expected the stack to be balanced");
+
+        assert!(matches!(
+            luau.pairs::<f64, f64>(1).next(),
+            Some(Err(LuauError::Conversion(_)))
+        ));
+        assert_eq!(luau.top(), 1, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn mutation() {
+        let luau = Luau::default();
+
+        luau.push(vec![1, 2, 3]);
+
+        // assigning existing fields is allowed during traversal
+        for pair in luau.pairs::<i32, i32>(1) {
+            let (k, v) = pair.unwrap();
+
+            luau.push(v * 2);
+            unsafe { lua_rawseti(luau.to_ptr(), 1, k) };
+        }
+
+        for (i, pair) in luau.raw_pairs::<i32, i32>(1).enumerate() {
+            luau.push(i as i32);
+            unsafe { lua_rawseti(luau.to_ptr(), 1, i as i32 + 10) };
+
+            assert!(pair.is_ok());
+        }
+
+        assert_eq!(luau.get::<Vec<i32>>(1).unwrap()[..3], [2, 4, 6]);
+        assert_eq!(luau.top(), 1, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn ipairs() {
+        let luau = Luau::default();
+
+        luau.push(vec![1.0, 2.0, 3.0]);
+
+        let values = luau.ipairs::<f64>(-1).collect::<Result<Vec<_>, _>>();
+
+        assert_eq!(values.unwrap(), vec![(1, 1.0), (2, 2.0), (3, 3.0)]);
+        assert_eq!(luau.top(), 1, "Expected the stack to be balanced");
+    }
+}
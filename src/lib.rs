@@ -1,49 +1,111 @@
+macro_rules! luau_stack_precondition {
+    ($cond:expr) => {
+        assert!(
+            $cond,
+            "Stack indicies should not exceed the top of the stack or extend below."
+        )
+    };
+}
+
+mod chunk;
 #[cfg(feature = "compiler")]
 pub mod compile;
 
+mod conversion;
+mod coverage;
+#[cfg(feature = "dap")]
+mod dap;
+mod debugger;
+mod error;
+mod executor;
 pub mod ffi;
+mod gc;
+mod interrupt;
+mod iter;
 mod libs;
 mod memory;
+mod profiler;
+mod refs;
+#[cfg(feature = "compiler")]
+mod require;
+mod sandbox;
 mod threads;
 mod userdata;
 
 use std::{
-    any::Any,
+    any::{Any, TypeId},
     cell::Cell,
+    collections::HashMap,
+    error::Error,
     ffi::{c_int, c_uint, c_void, CStr, CString},
     ptr::{null, null_mut},
     rc::Rc,
     slice,
+    task::Waker,
 };
 
-use ffi::{
-    luauconf::{LUAI_MAXCSTACK, LUA_MEMORY_CATEGORIES},
-    prelude::*,
-};
-use memory::{luau_alloc_cb, DefaultLuauAllocator};
+use debugger::{DebugHandler, StepState};
+use error::{invoke_callback, protected_call, protected_trampoline, traceback_handler, RaisedError};
+use ffi::{luauconf::LUAI_MAXCSTACK, prelude::*};
+use interrupt::InterruptCallback;
+use memory::{luau_alloc_cb, userthread_callback, DefaultLuauAllocator};
 use userdata::{
-    drop_userdata, dtor_rs_luau_userdata_callback, Userdata, UserdataBorrowError, UserdataRef,
-    UserdataRefMut, UD_TAG,
+    drop_userdata, dtor_rs_luau_userdata_callback, dtor_tagged_userdata, RustFunction, Userdata,
+    UserdataBorrowError, UserdataRef, UserdataRefMut, FN_TAG, RESERVED_TAG_START, UD_TAG,
 };
 
-pub use ffi::prelude::LuauStatus;
-pub use libs::LuauLibs;
-pub use memory::LuauAllocator;
-pub use threads::LuauThread;
+pub use userdata::{MetaMethod, UserData, UserDataRegistry};
 
-macro_rules! luau_stack_precondition {
-    ($cond:expr) => {
-        assert!(
-            $cond,
-            "Stack indicies should not exceed the top of the stack or extend below."
-        )
-    };
-}
+pub use chunk::{Chunk, ChunkMode};
+pub use conversion::{
+    Buffer, ConversionError, FromLuau, FromLuauMulti, IntoLuau, IntoLuauMulti, Vector,
+};
+pub use coverage::{CoverageReport, FunctionCoverage, SourceCoverage};
+#[cfg(feature = "dap")]
+pub use dap::DapServer;
+pub use debugger::{DebugAction, Debugger, Frame, StopReason};
+pub use error::{LuauError, Traceback};
+pub use executor::{LuauExecutor, TaskHandle, ThreadFuture};
+pub use ffi::prelude::{CoroutineStatus, LuauStatus};
+pub use interrupt::{InterruptAction, InterruptError};
+pub use iter::{IPairs, Pairs, RawPairs};
+pub use libs::LuauLibs;
+pub use memory::{LuauAllocator, MemoryCategory, MemoryCategoryGuard};
+pub use profiler::{FunctionTime, ProfileReport, ProfiledFunction, Profiler, StackSamples};
+pub use refs::{LuauFunction, LuauRef, LuauString, LuauTable};
+#[cfg(feature = "compiler")]
+pub use require::{
+    EmbeddedResolver, FileSystemResolver, MemoryResolver, ModuleResolver, ModuleSource,
+    RequireError,
+};
+pub use threads::{Generator, LuauThread, ResumeResult};
 
 struct AssociatedData {
     main_thread_rc: Rc<Cell<bool>>,
     allocator: Box<dyn LuauAllocator>,
     app_data: Option<Box<dyn Any>>,
+    userdata_metatables: HashMap<TypeId, RefIndex>,
+    userdata_tags: HashMap<TypeId, Tag>,
+    userdata_type_names: Vec<String>,
+    interrupt: Option<InterruptCallback>,
+    terminating: Option<InterruptError>,
+    memory_used: usize,
+    memory_limit: Option<usize>,
+    allocation_failed: bool,
+    memory_categories: HashMap<*mut _LuaState, c_int>,
+    memory_category_names: HashMap<c_int, String>,
+    #[cfg(feature = "compiler")]
+    require: Option<Rc<require::Require>>,
+    async_poll: Option<(*mut _LuaState, Waker)>,
+    pending_futures: RefIndex,
+    debug_handler: Option<DebugHandler>,
+    debug_step: Option<StepState>,
+    traceback: Option<Traceback>,
+    traceback_handler: RefIndex,
+    protected_trampoline: RefIndex,
+    profiler: Option<Rc<profiler::Sampler>>,
+    #[cfg(feature = "dap")]
+    dap_sources: Option<Rc<std::cell::RefCell<dap::Sources>>>,
 }
 
 #[cfg(feature = "codegen")]
@@ -59,24 +121,79 @@ pub struct Luau {
 }
 
 impl Luau {
-    unsafe fn new_state(allocator: impl LuauAllocator + 'static) -> *mut _LuaState {
-        let associated_data = Box::new(AssociatedData {
+    unsafe fn new_state(
+        allocator: impl LuauAllocator + 'static,
+        memory_limit: Option<usize>,
+    ) -> *mut _LuaState {
+        let associated_data = Box::into_raw(Box::new(AssociatedData {
             main_thread_rc: Rc::new(Cell::new(true)),
             app_data: None,
             allocator: Box::new(allocator),
-        });
+            userdata_metatables: HashMap::new(),
+            userdata_tags: HashMap::new(),
+            userdata_type_names: Vec::new(),
+            interrupt: None,
+            terminating: None,
+            memory_used: 0,
+            memory_limit,
+            allocation_failed: false,
+            memory_categories: HashMap::new(),
+            memory_category_names: HashMap::new(),
+            #[cfg(feature = "compiler")]
+            require: None,
+            async_poll: None,
+            pending_futures: RefIndex(LUA_NOREF),
+            debug_handler: None,
+            debug_step: None,
+            traceback: None,
+            traceback_handler: RefIndex(LUA_NOREF),
+            protected_trampoline: RefIndex(LUA_NOREF),
+            profiler: None,
+            #[cfg(feature = "dap")]
+            dap_sources: None,
+        }));
+
+        let state = lua_newstate(luau_alloc_cb, associated_data as _);
 
-        let state = lua_newstate(luau_alloc_cb, Box::into_raw(associated_data) as _);
+        if state.is_null() {
+            drop(Box::from_raw(associated_data));
+            return state;
+        }
 
         lua_setuserdatadtor(state, UD_TAG, Some(dtor_rs_luau_userdata_callback));
+        lua_setuserdatadtor(state, FN_TAG, Some(dtor_rs_luau_userdata_callback));
 
         (*lua_callbacks(state)).panic = Some(fatal_error_handler);
+        (*lua_callbacks(state)).userthread = Some(userthread_callback);
+
+        // the handler and trampoline are created while the memory limit is lifted as allocation failures here cannot be caught
+        let memory_limit = (*associated_data).memory_limit.take();
+
+        lua_pushcclosurek(state, traceback_handler, c"traceback".as_ptr(), 0, None);
+        (*associated_data).traceback_handler = lua_ref(state, -1);
+        lua_pop(state, 1);
+
+        // preallocated so protecting an operation cannot fail to allocate
+        lua_pushcclosurek(state, protected_trampoline, c"protected".as_ptr(), 0, None);
+        (*associated_data).protected_trampoline = lua_ref(state, -1);
+        lua_pop(state, 1);
+
+        // futures of async functions keyed by the waiting thread, weak so they are collected along with it
+        lua_createtable(state, 0, 0);
+        lua_createtable(state, 0, 1);
+        lua_pushlstring(state, c"k".as_ptr(), 1);
+        lua_setfield(state, -2, c"__mode".as_ptr());
+        lua_setmetatable(state, -2);
+        (*associated_data).pending_futures = lua_ref(state, -1);
+        lua_pop(state, 1);
+
+        (*associated_data).memory_limit = memory_limit;
 
         state
     }
 
     pub fn new(allocator: impl LuauAllocator + 'static) -> Self {
-        let state = unsafe { Self::new_state(allocator) };
+        let state = unsafe { Self::new_state(allocator, None) };
 
         if state.is_null() {
             panic!("Initialization of Luau failed");
@@ -85,6 +202,22 @@ impl Luau {
         Self { owned: true, state }
     }
 
+    /// Creates a state which may not allocate more than `limit` bytes, see `set_memory_limit`
+    ///
+    /// Returns `None` if the state could not be created within the limit.
+    pub fn with_memory_limit(
+        allocator: impl LuauAllocator + 'static,
+        limit: usize,
+    ) -> Option<Self> {
+        let state = unsafe { Self::new_state(allocator, Some(limit)) };
+
+        if state.is_null() {
+            None
+        } else {
+            Some(Self { owned: true, state })
+        }
+    }
+
     #[cfg(feature = "codegen")]
     /// Enables codegen for the given state
     pub fn enable_codegen(&self) {
@@ -165,7 +298,8 @@ impl Luau {
                         None,
                     );
                     self.push_string("");
-                    self.call(1, 0);
+                    self.call(1, 0)
+                        .expect("Luau libraries should open without error");
                 };
             };
 
@@ -178,7 +312,8 @@ impl Luau {
                         None,
                     );
                     self.push_string($idnt);
-                    self.call(1, 0);
+                    self.call(1, 0)
+                        .expect("Luau libraries should open without error");
                 };
             };
         }
@@ -265,11 +400,13 @@ impl Luau {
     }
 
     /// Produces an error with the value on the top of the stack
+    ///
+    /// Inside a Rust function pushed by this crate the error unwinds the Rust frames as a panic before it is thrown,
+    /// so it must not be caught with `catch_unwind`.
     pub fn error(&self) -> c_int {
         luau_stack_precondition!(self.check_index(-1));
 
-        // SAFETY: a value on the top of the stack exists as verified by the precondition
-        unsafe { lua_error(self.state) }
+        self.raise_error()
     }
 
     /// Returns the type of a luau value at `idx`
@@ -279,6 +416,37 @@ impl Luau {
         unsafe { lua_type(self.state, idx) }
     }
 
+    /// Returns the type of a luau value at `idx` or `LUA_TNONE` if `idx` is not a valid index
+    pub(crate) fn type_of_or_none(&self, idx: c_int) -> LuauType {
+        if !self.check_index(idx) {
+            return LuauType::LUA_TNONE;
+        }
+
+        // SAFETY: idx is validated above
+        unsafe { lua_type(self.state, idx) }
+    }
+
+    /// Converts `idx` into an index which does not depend on the top of the stack
+    pub fn abs_index(&self, idx: c_int) -> c_int {
+        if idx > 0 || lua_ispseudo(idx) {
+            idx
+        } else {
+            self.top() + idx + 1
+        }
+    }
+
+    /// Pushes a Rust value onto the stack returning the number of Luau values pushed
+    pub fn push<T: IntoLuauMulti>(&self, value: T) -> c_int {
+        value.push_multi(self)
+    }
+
+    /// Converts the value at `idx` into a Rust value
+    ///
+    /// Tuples are read from consecutive stack slots starting at `idx`
+    pub fn get<T: FromLuauMulti>(&self, idx: c_int) -> Result<T, ConversionError> {
+        T::from_luau_multi(self, self.abs_index(idx))
+    }
+
     /// Pops `n` values from the stack
     pub fn pop(&self, n: c_int) {
         // assert that the set position is not greater than the top
@@ -293,16 +461,22 @@ impl Luau {
         lua_upvalueindex(uv_idx)
     }
 
-    /// Sets the memory category for all allocations taking place after its set
-    pub fn set_memory_category(&self, cat: c_int) {
-        assert!(
-            cat < LUA_MEMORY_CATEGORIES,
-            "Memory category index must not exceed {LUA_MEMORY_CATEGORIES}"
-        );
+    /// Limits the total bytes allocated by the state, allocations past the limit raise a Luau memory error
+    ///
+    /// A limit below the current usage only prevents further growth. `None` removes the limit.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*self.get_associated_mut()).memory_limit = limit };
+    }
 
-        unsafe {
-            lua_setmemcat(self.state, cat);
-        }
+    /// Returns the memory limit set by `set_memory_limit` or `with_memory_limit`
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.get_associated().memory_limit
+    }
+
+    /// Returns the total bytes currently allocated by the state
+    pub fn memory_used(&self) -> usize {
+        self.get_associated().memory_used
     }
 
     pub fn check_index(&self, idx: c_int) -> bool {
@@ -349,18 +523,81 @@ impl Luau {
         LUA_GLOBALSINDEX
     }
 
+    /// Returns a handle to the globals table of the state
+    pub fn globals_table(&self) -> LuauTable {
+        luau_stack_precondition!(self.check_stack(1));
+
+        // SAFETY: the stack size is checked by the precondition
+        unsafe { lua_pushvalue(self.state, LUA_GLOBALSINDEX) };
+
+        let table = self
+            .get::<LuauTable>(-1)
+            .expect("Expected the globals to be a table");
+        self.pop(1);
+
+        table
+    }
+
+    /// Sets the global `name` to `value`
+    ///
+    /// May invoke a __newindex metamethod
+    pub fn set_global(&self, name: impl AsRef<[u8]>, value: impl IntoLuau) -> Result<(), LuauError> {
+        luau_stack_precondition!(self.check_stack(3));
+
+        // SAFETY: the trampoline only operates on the arguments it is called with
+        unsafe { self.push_raw_function(protected_set_global, None, 0, None) };
+        self.push_string(name);
+        value.push_to(self);
+
+        self.call(2, 0)
+    }
+
+    /// Converts the value of the global `name` into a Rust value
+    ///
+    /// May invoke a __index metamethod
+    pub fn get_global<T: FromLuau>(&self, name: impl AsRef<[u8]>) -> Result<T, LuauError> {
+        luau_stack_precondition!(self.check_stack(2));
+
+        // SAFETY: the trampoline only operates on the arguments it is called with
+        unsafe { self.push_raw_function(protected_get_global, None, 0, None) };
+        self.push_string(name);
+
+        self.call(1, 1)?;
+
+        let value = T::from_luau(self, self.top());
+        self.pop(1);
+
+        value.map_err(LuauError::Conversion)
+    }
+
+    /// Sets the global `name` to nil
+    pub fn remove_global(&self, name: impl AsRef<[u8]>) -> Result<(), LuauError> {
+        self.set_global(name, None::<bool>)
+    }
+
+    /// Sets a global for every name and value pair in `globals`, stopping at the first error
+    pub fn register_globals<K, V, I>(&self, globals: I) -> Result<(), LuauError>
+    where
+        K: AsRef<[u8]>,
+        V: IntoLuau,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (name, value) in globals {
+            self.set_global(name, value)?;
+        }
+
+        Ok(())
+    }
+
     pub fn check_args(&self, count: c_int, extra_message: Option<&CStr>) {
         if self.top() >= count {
             return;
         }
 
-        unsafe {
-            luaL_argerrorL(
-                self.state,
-                count - self.top(),
-                extra_message.map(CStr::as_ptr).unwrap_or(null()),
-            );
-        }
+        self.throw(RaisedError::ArgError(
+            count - self.top(),
+            extra_message.unwrap_or(c"value expected").to_owned(),
+        ));
     }
 
     /// Returns true if the value at `idx` is nil
@@ -466,15 +703,29 @@ impl Luau {
         let slice = str.as_ref();
 
         // SAFETY: the stack size is checked by the precondition
-        unsafe {
-            lua_pushlstring(self.state, slice.as_ptr() as _, slice.len());
-        }
+        self.protect(0, 1, |state| unsafe {
+            lua_pushlstring(state, slice.as_ptr() as _, slice.len());
+        });
     }
 
     /// Gets or tries to coerce a Luau value at `idx` into a slice of u8s
     pub fn to_str_slice(&self, idx: c_int) -> Option<&[u8]> {
         luau_stack_precondition!(self.check_index(idx));
 
+        if self.is_number(idx) {
+            luau_stack_precondition!(self.check_stack(1));
+
+            let idx = self.abs_index(idx);
+
+            // numbers are converted in place, which allocates the string
+            // SAFETY: idx is validated by the precondition and the converted copy replaces the number
+            unsafe { lua_pushvalue(self.state, idx) };
+            self.protect(1, 1, |state| unsafe {
+                lua_tolstring(state, -1, null_mut());
+            });
+            unsafe { lua_replace(self.state, idx) };
+        }
+
         // needs to have a lifetime to bind the result on a lifetime to prevent use after frees
         let mut len = 0;
         // SAFETY: idx is validated by the precondition
@@ -497,10 +748,20 @@ impl Luau {
     /// Gets or converts a Luau value at `idx` into a string with a reasonable format, will invoke __tostring metamethods.
     pub fn convert_to_str_slice(&self, idx: c_int) -> &[u8] {
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(3));
+
+        // SAFETY: idx is validated by the precondition
+        unsafe { lua_pushvalue(self.state, idx) };
+
+        // the string replaces the copied value on the top of the stack
+        self.protect(1, 1, |state| unsafe {
+            luaL_tolstring(state, -1, null_mut());
+            lua_remove(state, -2);
+        });
 
         unsafe {
             let mut len = 0;
-            let data = luaL_tolstring(self.state, idx, &raw mut len);
+            let data = lua_tolstring(self.state, -1, &raw mut len);
 
             if data.is_null() {
                 unreachable!("Luau string conversion returned NULL ptr");
@@ -512,15 +773,7 @@ impl Luau {
 
     /// Returns true if the userdata at `idx` is a userdata and is of type T
     pub fn is_userdata<T: Any>(&self, idx: c_int) -> bool {
-        luau_stack_precondition!(self.check_index(idx));
-
-        // SAFETY: idx is validated by the precondition and the behavior of userdata is checked
-        unsafe {
-            let userdata_ptr: *mut Userdata<()> =
-                lua_touserdatatagged(self.state, idx, UD_TAG) as _;
-
-            !userdata_ptr.is_null() && (*userdata_ptr).is::<T>()
-        }
+        self.get_userdata_ptr::<T>(idx).is_some()
     }
 
     /// Returns true if the userdata at `idx` is any type of userdata
@@ -535,14 +788,23 @@ impl Luau {
     pub fn push_userdata<T: Any>(&self, object: T) {
         luau_stack_precondition!(self.check_stack(1));
 
+        self.new_userdata(TypeId::of::<T>(), self.userdata_tag::<T>(), object);
+    }
+
+    /// Allocates a userdata with `tag` holding `object`
+    fn new_userdata<T>(&self, id: TypeId, tag: Tag, object: T) -> *mut Userdata<T> {
         // SAFETY: We allocate a DST as a userdata on a stack with the known proper size with our own tag.
-        // if the userdat allo
         // if our type T has drop glue then we will set the dtor field which will be invoked
         // we then construct a struct which has ownership of T
         // we need the dtor field because the struct is opaque elsewhere
+        // the metatable registered for the tag, if any, is assigned on creation
         unsafe {
-            let userdata_ptr: *mut Userdata<T> =
-                lua_newuserdatatagged(self.state, size_of::<Userdata<T>>(), UD_TAG).cast();
+            let mut userdata_ptr: *mut Userdata<T> = null_mut();
+
+            self.protect(0, 1, |state| {
+                userdata_ptr =
+                    lua_newuserdatataggedwithmetatable(state, size_of::<Userdata<T>>(), tag).cast();
+            });
 
             let dtor = if std::mem::needs_drop::<T>() {
                 let fn_item: unsafe fn(*mut Userdata<T>) = drop_userdata::<T>;
@@ -553,19 +815,169 @@ impl Luau {
             };
 
             userdata_ptr.write(Userdata {
-                id: object.type_id(),
+                id,
                 count_cell: Cell::new(0),
                 dtor,
                 inner: object,
             });
+
+            userdata_ptr
+        }
+    }
+
+    /// Pushes a value T as a userdata to Luau with the methods and metamethods registered by `UserData`
+    ///
+    /// The metatable is created the first time a type is pushed and reused afterwards.
+    pub fn create_userdata<T: UserData>(&self, object: T) {
+        luau_stack_precondition!(self.check_stack(2));
+
+        self.push_userdata(object);
+
+        // SAFETY: the userdata was pushed above
+        if unsafe { lua_getmetatable(self.state, -1) } != 0 {
+            // the metatable was assigned from the type's tag
+            self.pop(1);
+            return;
         }
+
+        self.push_userdata_metatable::<T>(None);
+        self.set_metatable(-2);
+    }
+
+    /// Pushes the cached metatable of T, creating it if it does not exist
+    fn push_userdata_metatable<T: UserData>(&self, name: Option<&str>) {
+        let cached = self
+            .get_associated()
+            .userdata_metatables
+            .get(&TypeId::of::<T>())
+            .copied();
+
+        if let Some(metatable) = cached {
+            self.get_reference(metatable);
+            return;
+        }
+
+        let mut registry = UserDataRegistry::new();
+        T::register(&mut registry);
+        registry.push_metatable(self);
+
+        if let Some(name) = name {
+            self.push_string(name);
+            self.set_field(-2, "__type");
+        }
+
+        let metatable = self.reference(-1);
+
+        // SAFETY: the associated data is not borrowed elsewhere while inserting
+        unsafe {
+            (*self.get_associated_mut())
+                .userdata_metatables
+                .insert(TypeId::of::<T>(), metatable);
+        }
+    }
+
+    /// Returns the userdata tag values of T are created with
+    fn userdata_tag<T: Any>(&self) -> Tag {
+        self.get_associated()
+            .userdata_tags
+            .get(&TypeId::of::<T>())
+            .copied()
+            .unwrap_or(UD_TAG)
+    }
+
+    /// Assigns T its own userdata tag so its values are identified by tag instead of comparing `TypeId`s
+    ///
+    /// Returns `None` if every tag has been assigned, in which case T keeps using the shared tag.
+    /// Registering a type which is already registered returns its existing tag.
+    pub fn register_userdata_type<T: Any>(&self, name: impl Into<String>) -> Option<Tag> {
+        // SAFETY: the associated data is not borrowed elsewhere while registering
+        let associated = unsafe { &mut *self.get_associated_mut() };
+
+        if let Some(tag) = associated.userdata_tags.get(&TypeId::of::<T>()) {
+            return Some(*tag);
+        }
+
+        // tags are assigned from 1 so tag 0 remains the default for untagged userdata
+        let tag = Tag(associated.userdata_type_names.len() as c_int + 1);
+
+        if tag.0 >= RESERVED_TAG_START {
+            return None;
+        }
+
+        if std::mem::needs_drop::<T>() {
+            // SAFETY: the tag is only used for values of T
+            unsafe { lua_setuserdatadtor(self.state, tag, Some(dtor_tagged_userdata::<T>)) };
+        }
+
+        associated.userdata_tags.insert(TypeId::of::<T>(), tag);
+        associated.userdata_type_names.push(name.into());
+
+        Some(tag)
+    }
+
+    /// Registers T with its own userdata tag and installs its `UserData` metatable on the tag
+    ///
+    /// The metatable's `__type` field is set to `name`. If no tag is available values of T share
+    /// the common tag and receive the metatable when created through `create_userdata`.
+    pub fn register_userdata<T: UserData>(&self, name: impl Into<String>) -> Option<Tag> {
+        luau_stack_precondition!(self.check_stack(2));
+
+        let name = name.into();
+
+        if self
+            .get_associated()
+            .userdata_tags
+            .contains_key(&TypeId::of::<T>())
+        {
+            return Some(self.userdata_tag::<T>());
+        }
+
+        let tag = self.register_userdata_type::<T>(name.as_str());
+
+        // SAFETY: the associated data is not borrowed elsewhere while removing
+        unsafe {
+            // a metatable created by `create_userdata` before registering has no `__type`
+            (*self.get_associated_mut())
+                .userdata_metatables
+                .remove(&TypeId::of::<T>());
+        }
+
+        self.push_userdata_metatable::<T>(Some(&name));
+
+        if let Some(tag) = tag {
+            // SAFETY: the metatable is on the top of the stack and the tag has no metatable assigned
+            unsafe { lua_setuserdatametatable(self.state, tag, -1) };
+        } else {
+            self.pop(1);
+        }
+
+        tag
+    }
+
+    /// Returns the names of types registered with their own tag, ordered by tag
+    ///
+    /// Passing these to `Compiler::set_userdata_types` lines up the type information with the tags.
+    pub fn userdata_type_names(&self) -> Vec<String> {
+        self.get_associated().userdata_type_names.clone()
     }
 
     fn get_userdata_ptr<T: Any>(&self, idx: c_int) -> Option<*mut Userdata<T>> {
         luau_stack_precondition!(self.check_index(idx));
 
+        let tag = self.userdata_tag::<T>();
+
         // SAFETY: We validate that the userdata at the checked idx is of the proper type T or null
         unsafe {
+            if tag != UD_TAG {
+                let userdata_ptr = lua_touserdatatagged(self.state, idx, tag);
+
+                // values of a registered type only use its tag
+                if !userdata_ptr.is_null() {
+                    return Some(userdata_ptr as _);
+                }
+            }
+
+            // values created before their type was registered use the shared tag
             let userdata_ptr: *mut Userdata<()> =
                 lua_touserdatatagged(self.state, idx, UD_TAG) as _;
 
@@ -659,11 +1071,11 @@ impl Luau {
     pub fn push_buffer(&mut self, size: usize) -> &mut [u8] {
         luau_stack_precondition!(self.check_stack(1));
 
-        unsafe {
-            let ptr: *mut u8 = lua_newbuffer(self.state, size) as _;
+        let mut ptr: *mut u8 = null_mut();
 
-            std::slice::from_raw_parts_mut(ptr, size)
-        }
+        self.protect(0, 1, |state| ptr = unsafe { lua_newbuffer(state, size) as _ });
+
+        unsafe { std::slice::from_raw_parts_mut(ptr, size) }
     }
 
     /// Pushes a slice to the Luau stack as a buffer
@@ -705,16 +1117,14 @@ impl Luau {
 
     /// Pushes an empty table to the Luau stack
     pub fn create_table(&self) {
-        unsafe {
-            lua_createtable(self.state, 0, 0);
-        }
+        self.create_table_with_capacity(0, 0);
     }
 
     /// Pushes an empty table to the Luau stack with a preallocated array portion of `narr` and an associative portion of `nrec`
     pub fn create_table_with_capacity(&self, narr: c_int, nrec: c_int) {
-        unsafe {
-            lua_createtable(self.state, narr, nrec);
-        }
+        self.protect(0, 1, |state| unsafe {
+            lua_createtable(state, narr, nrec);
+        });
     }
 
     pub fn shift(&self, to: c_int) {
@@ -728,9 +1138,18 @@ impl Luau {
     /// Makes a reference to the value at `idx` which can be retrieved from `get_reference`
     pub fn reference(&self, idx: c_int) -> RefIndex {
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(1));
+
+        let mut reference = RefIndex(LUA_NOREF);
+
+        // SAFETY: idx is checked, the registry may grow so the reference is made from a copy in protected mode
+        unsafe { lua_pushvalue(self.state, idx) };
+        self.protect(1, 0, |state| unsafe {
+            reference = lua_ref(state, -1);
+            lua_pop(state, 1);
+        });
 
-        // SAFETY: idx is checked
-        unsafe { lua_ref(self.state, idx) }
+        reference
     }
 
     /// Retrieves a reference from a RefIndex and pushes it to the top of the stack while returning the type's value
@@ -799,11 +1218,18 @@ impl Luau {
             "There must be a key and value on the stack to set table"
         );
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(3));
 
-        // SAFETY: idx is validated by the precondition
+        // SAFETY: idx is validated by the precondition and the copied table is beneath the key and value
         unsafe {
-            lua_settable(self.state, idx);
+            lua_pushvalue(self.state, idx);
+            lua_insert(self.state, -3);
         }
+
+        self.protect(3, 0, |state| unsafe {
+            lua_settable(state, -3);
+            lua_pop(state, 1);
+        });
     }
 
     /// Sets the value of t\[k\] with the value at the top of the stack where t is at the index and k is the value beneath the top of the stack.
@@ -815,11 +1241,19 @@ impl Luau {
             "There must be a key and value on the stack to set table"
         );
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(3));
 
-        // SAFETY: idx is validated by the precondition
+        // SAFETY: idx is validated by the precondition and the copied table is beneath the key and value
         unsafe {
-            lua_rawset(self.state, idx);
+            lua_pushvalue(self.state, idx);
+            lua_insert(self.state, -3);
         }
+
+        // readonly tables raise an error
+        self.protect(3, 0, |state| unsafe {
+            lua_rawset(state, -3);
+            lua_pop(state, 1);
+        });
     }
 
     /// Gets t\[k\] where k is the field string where t is the table at idx.
@@ -865,11 +1299,18 @@ impl Luau {
             "There must be a key on the stack to index the table"
         );
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(3));
 
-        // SAFETY: idx is validated by the precondition
+        // SAFETY: idx is validated by the precondition and the copied table is beneath the key
         unsafe {
-            lua_gettable(self.state, idx);
+            lua_pushvalue(self.state, idx);
+            lua_insert(self.state, -2);
         }
+
+        self.protect(2, 1, |state| unsafe {
+            lua_gettable(state, -2);
+            lua_remove(state, -2);
+        });
     }
 
     /// Gets the value of t\[k\] where t is the value at the index and k is the value on the top of the stack.
@@ -903,10 +1344,19 @@ impl Luau {
     /// Sets the metatable for individual tables and userdata or sets the metatable for an entire type.
     pub fn set_metatable(&self, idx: c_int) {
         luau_stack_precondition!(self.check_index(idx));
+        luau_stack_precondition!(self.check_stack(3));
 
+        // SAFETY: idx is validated by the precondition and the copied value is beneath the metatable
         unsafe {
-            lua_setmetatable(self.state, idx);
+            lua_pushvalue(self.state, idx);
+            lua_insert(self.state, -2);
         }
+
+        // readonly tables raise an error
+        self.protect(2, 0, |state| unsafe {
+            lua_setmetatable(state, -2);
+            lua_pop(state, 1);
+        });
     }
 
     /// Returns true if the value at idx is a vector, false otherwise
@@ -952,10 +1402,11 @@ impl Luau {
     }
 
     pub fn new_thread(&self) -> LuauThread {
-        unsafe {
-            let thread_ptr = lua_newthread(self.state);
-            LuauThread::from_ptr(thread_ptr, self.get_associated().main_thread_rc.clone())
-        }
+        let mut thread_ptr = null_mut();
+
+        self.protect(0, 1, |state| thread_ptr = unsafe { lua_newthread(state) });
+
+        unsafe { LuauThread::from_ptr(thread_ptr, self.get_associated().main_thread_rc.clone()) }
     }
 
     pub fn get_thread(&self, idx: c_int) -> Option<LuauThread> {
@@ -991,9 +1442,34 @@ impl Luau {
 
     /// Resumes the given Luau thread with the number of arguments.
     ///
-    /// Will resume the function on the top of the given Luau thread's execution stack
-    pub fn resume(&self, luau_thread: &LuauThread, nargs: c_int) -> LuauStatus {
-        unsafe { lua_resume(luau_thread.get_state().state, self.state, nargs) }
+    /// Will resume the function on the top of the given Luau thread's execution stack.
+    /// On failure the error value is removed from the thread's stack.
+    pub fn resume(&self, luau_thread: &LuauThread, nargs: c_int) -> Result<LuauStatus, LuauError> {
+        let thread = luau_thread.get_state();
+
+        // SAFETY: the associated data is only borrowed for the reset
+        unsafe { (*self.get_associated_mut()).allocation_failed = false };
+
+        // SAFETY: Luau validates the thread can be resumed and raises an error otherwise
+        let status = protected_call(|| unsafe { lua_resume(thread.state, self.state, nargs) });
+        let terminated = self.take_termination();
+
+        match status {
+            LuauStatus::LUA_OK | LuauStatus::LUA_YIELD | LuauStatus::LUA_BREAK => {
+                match terminated {
+                    None => Ok(status),
+                    // a terminated thread which returned or yielded cannot be resumed again
+                    Some(err) => {
+                        // SAFETY: the thread is not running as it returned control to this state
+                        unsafe { lua_resetthread(thread.state) };
+
+                        Err(LuauError::External(Box::new(err)))
+                    }
+                }
+            }
+            // the thread's call stack is left in place on error so the traceback can be read
+            _ => Err(thread.pop_error(status, Some(thread.traceback(0)))),
+        }
     }
 
     /// Returns true if the value at `idx` is a function, false otherwise
@@ -1022,9 +1498,9 @@ impl Luau {
         );
 
         // SAFETY: upvalue count and stack size are validated as a precondition and assert
-        unsafe {
+        self.protect(num_upvals, 1, |state| unsafe {
             lua_pushcclosurek(
-                self.state,
+                state,
                 func,
                 if let Some(name) = debug_name {
                     name.as_ptr()
@@ -1034,7 +1510,7 @@ impl Luau {
                 num_upvals,
                 continuation,
             );
-        }
+        });
     }
 
     /// Pushes a Rust function into Luau with an associated continuation
@@ -1062,20 +1538,17 @@ impl Luau {
             cont: Cont,
         }
 
-        let call_state = Box::new(CallState { func, cont });
-
         unsafe extern "C-unwind" fn invoke_fn<
             F: FnMut(&Luau) -> c_int,
             Cont: FnMut(&Luau, LuauStatus) -> c_int,
         >(
             state: *mut _LuaState,
         ) -> c_int {
-            let call_state =
-                lua_tolightuserdata(state, lua_upvalueindex(1)).cast::<CallState<F, Cont>>();
+            invoke_callback(state, |luau| {
+                let mut call_state = borrow_function::<CallState<F, Cont>>(luau);
 
-            let luau = Luau::from_ptr(state);
-
-            ((*call_state).func)(&luau)
+                (call_state.func)(luau)
+            })
         }
 
         unsafe extern "C-unwind" fn invoke_continuation<
@@ -1085,16 +1558,21 @@ impl Luau {
             state: *mut _LuaState,
             status: c_int,
         ) -> c_int {
-            let call_state =
-                lua_tolightuserdata(state, lua_upvalueindex(1)).cast::<CallState<F, Cont>>();
+            invoke_callback(state, |luau| {
+                let mut call_state = borrow_function::<CallState<F, Cont>>(luau);
 
-            let luau = Luau::from_ptr(state);
-            ((*call_state).cont)(&luau, std::mem::transmute::<c_int, LuauStatus>(status))
+                (call_state.cont)(luau, std::mem::transmute::<c_int, LuauStatus>(status))
+            })
         }
 
-        unsafe {
-            lua_pushlightuserdata(self.state, Box::into_raw(call_state) as _);
+        self.new_userdata(
+            TypeId::of::<RustFunction>(),
+            FN_TAG,
+            CallState { func, cont },
+        );
 
+        // SAFETY: the closure userdata is the last upvalue as expected by `borrow_function`
+        unsafe {
             self.push_raw_function(
                 invoke_fn::<F, Cont>,
                 debug_name,
@@ -1106,7 +1584,12 @@ impl Luau {
 
     /// Pushes a Rust function into Luau
     ///
-    /// This function wraps a Rust function to allow closures to capture values, to avoid this minor overhead you can use `push_function_raw`
+    /// This function wraps a Rust function to allow closures to capture values, to avoid this minor overhead you can use `push_function_raw`.
+    /// The closure is dropped when the function is garbage collected.
+    ///
+    /// A panic inside `func` is raised as a Luau error and resumed once it reaches the Rust caller of `call` or `resume`.
+    /// Operations which may raise a Luau error, such as invoking metamethods, run in protected mode while `func` runs
+    /// and their errors are raised from the function.
     pub fn push_function<F: FnMut(&Luau) -> i32>(
         &self,
         func: F,
@@ -1120,63 +1603,163 @@ impl Luau {
 
         luau_stack_precondition!(self.check_stack(2));
 
-        let func_box = Box::new(func);
-
         unsafe extern "C-unwind" fn invoke_fn<T: FnMut(&Luau) -> i32>(
             state: *mut _LuaState,
         ) -> c_int {
-            let func = lua_tolightuserdata(state, lua_upvalueindex(1)).cast::<T>();
+            invoke_callback(state, |luau| {
+                let mut func = borrow_function::<T>(luau);
 
-            let state = Luau::from_ptr(state);
-            (*func)(&state)
+                (*func)(luau)
+            })
         }
 
-        unsafe {
-            lua_pushlightuserdata(self.state, Box::into_raw(func_box) as _);
+        self.new_userdata(TypeId::of::<RustFunction>(), FN_TAG, func);
 
+        // SAFETY: the closure userdata is the last upvalue as expected by `borrow_function`
+        unsafe {
             self.push_raw_function(invoke_fn::<F>, debug_name, 1 + num_upvals, None);
         }
     }
 
-    /// Calls the Luau function at the top of the stack returning the status of the Luau state when it returns
-    pub fn call(&self, nargs: c_int, nresults: c_int) -> LuauStatus {
-        assert!(
-            self.is_function(-1),
-            "The value at top of stack must be a function"
+    /// Pushes a Rust function into Luau which converts its arguments and return values
+    ///
+    /// Arguments are read starting at the first argument, if they cannot be converted a Luau argument error is raised.
+    /// Values returned by `func` are pushed as the function's results and errors are raised as Luau errors.
+    pub fn create_function<A, R, E, F>(&self, mut func: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, A) -> Result<R, E>,
+    {
+        self.push_function(
+            move |luau| {
+                let args = match A::from_luau_multi(luau, 1) {
+                    Ok(args) => args,
+                    Err(err) => luau.argument_error(&err),
+                };
+
+                match func(luau, args) {
+                    Ok(results) => results.push_multi(luau),
+                    Err(err) => luau.raise_external(err.into()),
+                }
+            },
+            None,
+            0,
         );
+    }
+
+    /// Raises a Luau argument error for the argument at the index of a failed conversion
+    ///
+    /// Errors without a reason are reported as type errors naming the expected and received types.
+    pub fn argument_error(&self, err: &ConversionError) -> ! {
+        self.throw(match &err.reason {
+            None => RaisedError::TypeError(err.index, CString::new(err.expected).unwrap_or_default()),
+            Some(reason) => {
+                RaisedError::ArgError(err.index, CString::new(reason.as_str()).unwrap_or_default())
+            }
+        })
+    }
 
+    /// Calls the Luau function beneath `nargs` arguments on the stack in protected mode.
+    ///
+    /// On failure the function and its arguments are removed from the stack and no results are pushed.
+    /// If a Rust function panicked during the call the panic is resumed after the stack has been restored.
+    pub fn call(&self, nargs: c_int, nresults: c_int) -> Result<(), LuauError> {
         assert!(
-            self.top() >= nargs,
+            self.top() > nargs,
             "Argument count may not exceed the total stack size"
         );
 
+        assert!(
+            self.is_function(-(nargs + 1)),
+            "The value beneath the arguments must be a function"
+        );
+
         luau_stack_precondition!(self.check_stack(nresults));
 
-        unsafe { lua_pcall(self.state, nargs, nresults, 0) }
+        let base = self.top() - nargs - 1;
+
+        // failures caught by scripts in earlier calls must not be mistaken for this call's errors
+        // SAFETY: the associated data is only borrowed for the reset
+        unsafe { (*self.get_associated_mut()).allocation_failed = false };
+
+        // the message handler captures the traceback before the stack unwinds
+        self.get_reference(self.get_associated().traceback_handler);
+
+        // SAFETY: the function and argument count are validated by the asserts and the handler is beneath the function
+        let status = protected_call(|| unsafe {
+            lua_insert(self.state, base + 1);
+            lua_pcall(self.state, nargs, nresults, base + 1)
+        });
+
+        // SAFETY: the handler is beneath the results or error value
+        unsafe { lua_remove(self.state, base + 1) };
+
+        let terminated = self.take_termination();
+        // SAFETY: the associated data is only borrowed for the take
+        let traceback = unsafe { (*self.get_associated_mut()).traceback.take() };
+
+        match status {
+            LuauStatus::LUA_OK => match terminated {
+                None => Ok(()),
+                // the script caught the error and returned before reaching another safepoint
+                Some(err) => {
+                    self.pop(self.top() - base);
+                    Err(LuauError::External(Box::new(err)))
+                }
+            },
+            // the handler turns memory errors into runtime errors which `pop_error` maps back to `LuauError::Memory`
+            _ => Err(self.pop_error(status, traceback)),
+        }
     }
 
     /// Loads bytecode into the VM and pushes a function to the stack
-    pub fn load(&self, chunk_name: Option<&CStr>, bytecode: &[u8], env: c_int) -> Result<(), &str> {
+    ///
+    /// On failure nothing is left on the stack and the loader's message is returned as a `LuauError::Syntax`.
+    pub fn load(
+        &self,
+        chunk_name: Option<&CStr>,
+        bytecode: &[u8],
+        env: c_int,
+    ) -> Result<(), LuauError> {
         // specifically allow env 0
         luau_stack_precondition!(env == 0 || self.check_index(env));
         luau_stack_precondition!(self.check_stack(2));
 
-        let success = unsafe {
-            luau_load(
-                self.state,
+        let mut success = 0;
+
+        if env != 0 {
+            // SAFETY: env is validated by the precondition, the copy is passed to the protected load
+            unsafe { lua_pushvalue(self.state, env) };
+        }
+
+        // the function or message replaces the copied environment
+        self.protect((env != 0) as c_int, 1, |state| unsafe {
+            success = luau_load(
+                state,
                 chunk_name.unwrap_or(c"").as_ptr(),
                 bytecode.as_ptr() as _,
                 bytecode.len(),
-                env,
-            )
-        };
+                if env != 0 { -1 } else { 0 },
+            );
+
+            if env != 0 {
+                lua_remove(state, -2);
+            }
+        });
 
         if success == 0 {
+            #[cfg(feature = "dap")]
+            dap::chunk_loaded(self, chunk_name.unwrap_or(c""));
+
             Ok(())
         } else {
-            dbg!(self.top());
-            // we have an error and know its ascii
-            Err(self.to_str(-1).unwrap().unwrap())
+            let message =
+                String::from_utf8_lossy(self.to_str_slice(-1).unwrap_or_default()).into_owned();
+            self.pop(1);
+
+            Err(LuauError::Syntax(message))
         }
     }
 
@@ -1197,6 +1780,47 @@ impl Luau {
     }
 }
 
+/// Mutably borrows the closure of the running Rust function, which is stored as its last upvalue
+///
+/// Raises an error if the closure is already running as it cannot be borrowed twice.
+///
+/// # Safety
+/// Must only be called from a function pushed by `push_function` or `push_function_continuation` with a closure of type T
+unsafe fn borrow_function<T>(luau: &Luau) -> UserdataRefMut<T> {
+    let mut userdata_ptr = lua_touserdatatagged(luau.state, lua_upvalueindex(1), FN_TAG);
+
+    if userdata_ptr.is_null() {
+        // user upvalues come first so the closure is only the first upvalue if there are none
+        let mut ar: LuaDebug = std::mem::zeroed();
+        lua_getinfo(luau.state, 0, c"u".as_ptr(), &raw mut ar);
+
+        userdata_ptr =
+            lua_touserdatatagged(luau.state, lua_upvalueindex(ar.nupvals as c_int), FN_TAG);
+    }
+
+    assert!(
+        !userdata_ptr.is_null(),
+        "Expected the closure of a Rust function as its last upvalue"
+    );
+
+    match UserdataRefMut::try_from_ptr(userdata_ptr.cast()) {
+        Ok(func) => func,
+        Err(_) => luau.raise_message(
+            "cannot call a Rust function while it is already running".to_string(),
+        ),
+    }
+}
+
+unsafe extern "C-unwind" fn protected_get_global(state: *mut _LuaState) -> c_int {
+    lua_gettable(state, LUA_GLOBALSINDEX);
+    1
+}
+
+unsafe extern "C-unwind" fn protected_set_global(state: *mut _LuaState) -> c_int {
+    lua_settable(state, LUA_GLOBALSINDEX);
+    0
+}
+
 // TODO: do this
 unsafe extern "C-unwind" fn fatal_runtime_error_handler(state: *mut _LuaState) -> c_int {
     let luau = unsafe { Luau::from_ptr(state) };
@@ -1212,7 +1836,7 @@ unsafe extern "C-unwind" fn fatal_error_handler(state: *mut _LuaState, status: L
     match status {
         // Unhandled runtime error
         LuauStatus::LUA_ERRRUN => fatal_runtime_error_handler(state),
-        // memory allocation error, just die
+        // memory allocation error outside of a protected call, just die
         LuauStatus::LUA_ERRMEM => std::process::abort(),
         // some error handling mechanism errored
         LuauStatus::LUA_ERRERR => panic!("Error originating from error handling mechanism"),
@@ -1265,11 +1889,13 @@ mod tests {
     use std::{
         ffi::{c_int, c_void},
         hint::black_box,
+        panic::{catch_unwind, AssertUnwindSafe},
         rc::Rc,
     };
 
     use crate::{
-        Luau, LuauAllocator, _LuaState,
+        lua_gc, GCOperation, Luau, LuauAllocator, LuauError, LuauFunction, LuauRef, LuauTable,
+        _LuaState,
         compile::Compiler,
         lua_error, lua_tonumber, lua_upvalueindex,
         userdata::{UserdataBorrowError, UserdataRef},
@@ -1280,16 +1906,16 @@ mod tests {
     fn try_test() {
         let luau = Luau::default();
 
-        let status = try_luau!(luau, {
+        let result = try_luau!(luau, {
             luau.push_boolean(true);
             luau.error()
         });
 
         assert!(
-            matches!(status, LuauStatus::LUA_ERRRUN),
-            "Expected a runtime error"
+            matches!(&result, Err(LuauError::Runtime { message, .. }) if message == "(error object is a boolean value)"),
+            "Expected a runtime error describing the error object"
         );
-        assert!(luau.to_boolean(-1), "Expected the boolean to be true");
+        assert_eq!(luau.top(), 0, "Expected the error value to be popped");
     }
 
     #[test]
@@ -1340,7 +1966,7 @@ mod tests {
 
         luau.codegen(-1);
 
-        luau.call(0, 0);
+        assert!(luau.call(0, 0).is_ok(), "Expected the call to succeed");
     }
 
     #[test]
@@ -1351,9 +1977,10 @@ mod tests {
 
         // might change depending on luau updates
         assert!(
-            load_result.is_err_and(|v| v == r#"[string ""]Error!"#),
+            matches!(&load_result, Err(LuauError::Syntax(message)) if message == r#"[string ""]Error!"#),
             "Expected load result to be an error and be the correct error message."
         );
+        assert_eq!(luau.top(), 0, "Expected the error message to be popped");
     }
 
     #[test]
@@ -1468,8 +2095,10 @@ mod tests {
             0,
         );
 
-        luau.resume(&thread, 0);
-
+        assert!(
+            matches!(luau.resume(&thread, 0), Ok(LuauStatus::LUA_OK)),
+            "Expected thread to finish"
+        );
         assert!(was_called, "Expected thread function to be called");
     }
 
@@ -1482,6 +2111,302 @@ mod tests {
         assert_eq!(luau.get_app_data::<bool>().copied(), Some(true))
     }
 
+    #[test]
+    fn function_drop() {
+        let luau = Luau::default();
+
+        let captured = Rc::new(());
+        let moved = captured.clone();
+
+        luau.push_function(
+            move |_| {
+                black_box(&moved);
+                0
+            },
+            None,
+            0,
+        );
+
+        assert!(luau.call(0, 0).is_ok(), "Expected the call to succeed");
+        assert_eq!(Rc::strong_count(&captured), 2);
+
+        unsafe { lua_gc(luau.to_ptr(), GCOperation::LUA_GCCOLLECT, 0) };
+
+        assert_eq!(
+            Rc::strong_count(&captured),
+            1,
+            "Expected the closure to be dropped when the function is collected"
+        );
+
+        let moved = captured.clone();
+
+        luau.push_function_continuation(
+            move |_| {
+                black_box(&moved);
+                0
+            },
+            None,
+            0,
+            |_, _| 0,
+        );
+
+        drop(luau);
+
+        assert_eq!(
+            Rc::strong_count(&captured),
+            1,
+            "Expected the closure to be dropped when the state is closed"
+        );
+    }
+
+    #[test]
+    fn function_recursion() {
+        let luau = Luau::default();
+
+        luau.push_function(
+            |luau| {
+                let function = luau.get_global::<LuauFunction>("f").unwrap();
+
+                // the closure is already borrowed by the outer call
+                let err = function.call::<_, ()>(()).unwrap_err();
+                assert!(err.to_string().contains("already running"), "{err}");
+
+                0
+            },
+            None,
+            0,
+        );
+        luau.set_field(luau.globals(), "f");
+
+        let function = luau.get_global::<LuauFunction>("f").unwrap();
+
+        assert!(
+            function.call::<_, ()>(()).is_ok(),
+            "Expected the recursive call to error without failing the outer call"
+        );
+    }
+
+    #[test]
+    fn function_panic() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        luau.push_function(|_| panic!("boom"), None, 0);
+        let function = luau.get::<LuauFunction>(-1).unwrap();
+
+        // Luau code sees the panic as an error carrying its message
+        let pcall = luau.get_global::<LuauFunction>("pcall").unwrap();
+        let tostring = luau.get_global::<LuauFunction>("tostring").unwrap();
+
+        let (ok, err) = pcall.call::<_, (bool, LuauRef)>(&function).unwrap();
+
+        assert!(!ok);
+        assert_eq!(
+            tostring.call::<_, String>(err).unwrap(),
+            "Rust function panicked: boom"
+        );
+
+        // the panic resumes once it reaches the Rust caller
+        let payload = catch_unwind(AssertUnwindSafe(|| luau.call(0, 0)))
+            .expect_err("Expected the panic to be resumed");
+
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+        assert_eq!(luau.top(), 0, "Expected the function to be popped");
+    }
+
+    #[test]
+    fn nested_function_error() {
+        let luau = Luau::default();
+
+        luau.create_table();
+        luau.create_table();
+        luau.push_function(
+            |luau| {
+                luau.push_string("cannot assign");
+                luau.error()
+            },
+            None,
+            0,
+        );
+        luau.set_field(-2, "__newindex");
+        luau.set_metatable(-2);
+
+        let table = luau.get::<LuauTable>(-1).unwrap();
+        luau.pop(1);
+
+        // the metamethod is called directly by the outer function without a protected call in between
+        luau.push_function(
+            move |luau| {
+                luau.push(&table);
+                luau.push_number(1.0);
+                luau.set_field(-2, "x");
+
+                0
+            },
+            None,
+            0,
+        );
+
+        assert!(matches!(
+            luau.call(0, 0),
+            Err(LuauError::Runtime { message, .. }) if message == "cannot assign"
+        ));
+        assert_eq!(luau.top(), 0, "Expected the error value to be popped");
+    }
+
+    #[test]
+    fn protected_operations() {
+        let luau = Luau::default();
+
+        luau.create_table();
+        luau.set_readonly(-1, true);
+        let readonly = luau.get::<LuauTable>(-1).unwrap();
+        luau.pop(1);
+
+        luau.push_function(
+            move |luau| {
+                luau.push(&readonly);
+                luau.push_number(1.0);
+                luau.set_field(-2, "x");
+
+                0
+            },
+            None,
+            0,
+        );
+
+        assert!(matches!(
+            luau.call(0, 0),
+            Err(LuauError::Runtime { message, .. }) if message.contains("readonly")
+        ));
+
+        luau.create_table();
+        luau.create_table();
+        luau.push_function(
+            |luau| {
+                luau.push_string("no field");
+                luau.error()
+            },
+            None,
+            0,
+        );
+        luau.set_field(-2, "__index");
+        luau.set_metatable(-2);
+
+        let table = luau.get::<LuauTable>(-1).unwrap();
+        luau.pop(1);
+
+        luau.push_function(
+            move |luau| {
+                luau.push(&table);
+                luau.get_field(-1, "x");
+
+                1
+            },
+            None,
+            0,
+        );
+
+        assert!(matches!(
+            luau.call(0, 1),
+            Err(LuauError::Runtime { message, .. }) if message == "no field"
+        ));
+        assert_eq!(luau.top(), 0, "Expected the error values to be popped");
+    }
+
+    #[test]
+    fn raise_error() {
+        #[derive(Debug, PartialEq)]
+        struct CustomError(i32);
+
+        impl std::fmt::Display for CustomError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "custom error {}", self.0)
+            }
+        }
+
+        impl std::error::Error for CustomError {}
+
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        luau.push_function(|luau| luau.raise(CustomError(1)), None, 0);
+        let function = luau.get::<LuauFunction>(-1).unwrap();
+        luau.pop(1);
+
+        let pcall = luau.get_global::<LuauFunction>("pcall").unwrap();
+        let tostring = luau.get_global::<LuauFunction>("tostring").unwrap();
+
+        let (ok, err) = pcall.call::<_, (bool, LuauRef)>(&function).unwrap();
+
+        assert!(!ok);
+        assert_eq!(tostring.call::<_, String>(err).unwrap(), "custom error 1");
+
+        let err = function.call::<_, ()>(()).unwrap_err();
+
+        assert_eq!(err.downcast_ref::<CustomError>(), Some(&CustomError(1)));
+        assert!(err.downcast_ref::<std::fmt::Error>().is_none());
+        assert_eq!(
+            err.downcast::<CustomError>().ok(),
+            Some(Box::new(CustomError(1)))
+        );
+    }
+
+    #[test]
+    fn call_traceback() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let source = "local function inner()
+    error('boom')
+end
+local function outer()
+    inner()
+end
+outer()";
+
+        let bytecode = Compiler::new().compile(source);
+        luau.load(Some(c"@script.luau"), bytecode.bytecode().unwrap(), 0)
+            .unwrap();
+
+        let err = luau.call(0, 0).unwrap_err();
+
+        let LuauError::Runtime {
+            traceback: Some(traceback),
+            ..
+        } = &err
+        else {
+            panic!("Expected a runtime error with a traceback, got {err:?}");
+        };
+
+        let frames = traceback
+            .frames
+            .iter()
+            .map(|frame| (frame.name.as_deref(), frame.line))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            frames,
+            [
+                (Some("error"), None),
+                (Some("inner"), Some(2)),
+                (Some("outer"), Some(5)),
+                (None, Some(7))
+            ]
+        );
+        assert_eq!(traceback.frames[1].source, "@script.luau");
+        assert!(
+            err.to_string().contains(
+                "stack traceback:\n[C] function error\nscript.luau:2 function inner\nscript.luau:5 function outer\nscript.luau:7"
+            ),
+            "Unexpected error format {err}"
+        );
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
     #[test]
     fn function_upvalue_test() {
         let luau = Luau::default();
@@ -1502,7 +2427,7 @@ mod tests {
             3,
         );
 
-        luau.call(0, 0);
+        assert!(luau.call(0, 0).is_ok(), "Expected the call to succeed");
     }
 
     #[test]
@@ -1525,7 +2450,7 @@ mod tests {
             luau.push_raw_function(test_extern_fn, Some(c"test"), 3, None);
         }
 
-        luau.call(0, 0);
+        assert!(luau.call(0, 0).is_ok(), "Expected the call to succeed");
     }
 
     #[test]
@@ -1551,8 +2476,8 @@ mod tests {
         );
         thread_state.load(None, bc.bytecode().unwrap(), 0).unwrap();
 
-        luau.resume(&thread, 1);
-        luau.resume(&thread, 0);
+        assert!(matches!(luau.resume(&thread, 1), Ok(LuauStatus::LUA_YIELD)));
+        assert!(matches!(luau.resume(&thread, 0), Ok(LuauStatus::LUA_OK)));
 
         assert!(cont, "Expected that the continuation would be called.")
     }
@@ -1581,6 +2506,112 @@ mod tests {
         };
     }
 
+    #[test]
+    fn memory_limit() {
+        let luau = Luau::default();
+        let compiler = Compiler::new();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let limit = luau.memory_used() + 256 * 1024;
+        luau.set_memory_limit(Some(limit));
+
+        assert_eq!(luau.memory_limit(), Some(limit));
+
+        // scripts can catch the memory error
+        let bc = compiler.compile(
+            "local ok, err = pcall(function() local t = {} for i = 1, 1e7 do t[i] = i end end) return ok, err",
+        );
+        luau.load(None, bc.bytecode().unwrap(), 0).unwrap();
+
+        assert!(luau.call(0, 2).is_ok());
+        assert_eq!(
+            luau.get::<(bool, String)>(1),
+            Ok((false, "not enough memory".to_string()))
+        );
+        luau.pop(2);
+
+        // allocations made by Rust functions fail the same way
+        luau.push_function(
+            |luau| {
+                luau.push_string(vec![0; 1024 * 1024]);
+                1
+            },
+            None,
+            0,
+        );
+
+        assert!(matches!(luau.call(0, 1), Err(LuauError::Memory)));
+        assert!(luau.memory_used() <= limit);
+
+        luau.set_memory_limit(None);
+        luau.push_function(
+            |luau| {
+                luau.push_string(vec![0; 1024 * 1024]);
+                1
+            },
+            None,
+            0,
+        );
+
+        assert!(luau.call(0, 1).is_ok(), "Expected the limit to be removed");
+    }
+
+    #[test]
+    fn memory_limit_handler() {
+        let luau = Luau::default();
+        let compiler = Compiler::new();
+
+        let bc = compiler.compile("local t = {} for i = 1, 1e7 do t[i] = i end");
+        luau.load(None, bc.bytecode().unwrap(), 0).unwrap();
+
+        luau.set_memory_limit(Some(luau.memory_used() + 256 * 1024));
+
+        // the error passes through the traceback handler which would otherwise report a runtime error
+        assert!(matches!(luau.call(0, 0), Err(LuauError::Memory)));
+        assert_eq!(luau.top(), 0);
+
+        // a script raising the same message is still a runtime error
+        luau.set_memory_limit(None);
+
+        let bc = compiler.compile("error('not enough memory', 0)");
+        luau.load(None, bc.bytecode().unwrap(), 0).unwrap();
+
+        assert!(matches!(luau.call(0, 0), Err(LuauError::Runtime { .. })));
+    }
+
+    #[test]
+    fn memory_limit_nested() {
+        let luau = Luau::default();
+        let compiler = Compiler::new();
+
+        let bc = compiler.compile("local t = {} for i = 1, 1e7 do t[i] = i end");
+        luau.load(None, bc.bytecode().unwrap(), 0).unwrap();
+        let grow = luau.get::<LuauFunction>(-1).unwrap();
+        luau.pop(1);
+
+        let limit = luau.memory_used() + 256 * 1024;
+        luau.set_memory_limit(Some(limit));
+
+        // Lua called from a Rust function reports the failure to the function instead of aborting
+        luau.push_function(
+            move |luau| {
+                assert!(matches!(grow.call::<_, ()>(()), Err(LuauError::Memory)));
+
+                luau.push_boolean(true);
+                1
+            },
+            None,
+            0,
+        );
+
+        assert!(luau.call(0, 1).is_ok());
+        assert_eq!(luau.get::<bool>(-1), Ok(true));
+        luau.pop(1);
+
+        assert!(luau.memory_used() <= limit);
+    }
+
     #[test]
     fn function_check() {
         let luau = Luau::default();
@@ -1595,10 +2626,10 @@ mod tests {
             0,
         );
 
-        let status = luau.call(0, 0);
+        let result = luau.call(0, 0);
 
         assert!(
-            matches!(status, LuauStatus::LUA_ERRRUN),
+            matches!(result, Err(LuauError::Runtime { .. })),
             "Expected there to be a runtime error."
         );
 
@@ -1612,15 +2643,106 @@ mod tests {
             0,
         );
 
-        let status = luau.call(0, 0);
+        let result = luau.call(0, 0);
 
         assert!(
-            matches!(status, LuauStatus::LUA_ERRRUN),
-            "Expected there to be a runtime error."
+            matches!(
+                result,
+                Err(LuauError::Runtime { ref message, .. }) if message == "invalid argument #1 to 'test' (value expected)"
+            ),
+            "Expected there to be a runtime error, got {result:?}"
         );
 
     }
 
+    #[test]
+    fn typed_functions() {
+        let luau = Luau::default();
+
+        let mut calls = 0;
+        luau.create_function(|_, (a, b): (f64, String)| {
+            calls += 1;
+
+            if b.is_empty() {
+                Err("expected a non-empty string")
+            } else {
+                Ok((a > 1.0, b.repeat(2)))
+            }
+        });
+
+        let function = luau.reference(-1);
+
+        luau.push((2.0, "ab"));
+
+        assert!(luau.call(2, 2).is_ok());
+        assert_eq!(luau.get::<(bool, String)>(1), Ok((true, "abab".to_string())));
+        luau.pop(2);
+
+        luau.get_reference(function);
+        luau.push((1.0, true));
+
+        assert!(matches!(
+            luau.call(2, 0),
+            Err(LuauError::Runtime { message, .. }) if message.contains("string expected, got boolean")
+        ));
+
+        luau.get_reference(function);
+        luau.push((1.0, ""));
+
+        assert!(matches!(
+            luau.call(2, 0),
+            Err(LuauError::External(err)) if err.to_string() == "expected a non-empty string"
+        ));
+        assert_eq!(luau.top(), 0, "Expected the error value to be popped");
+
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn globals() {
+        let luau = Luau::default();
+
+        luau.set_global("a", 1.0).unwrap();
+        luau.register_globals([("b", "two"), ("c", "three")]).unwrap();
+
+        assert_eq!(luau.get_global::<f64>("a").unwrap(), 1.0);
+        assert_eq!(luau.get_global::<String>("c").unwrap(), "three");
+        assert_eq!(luau.globals_table().get::<_, String>("b").unwrap(), "two");
+
+        luau.remove_global("a").unwrap();
+
+        assert_eq!(luau.get_global::<Option<f64>>("a").unwrap(), None);
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn globals_metamethod_error() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        luau.chunk(
+            "setmetatable(_G, {
+                __index = function() error('no global') end,
+                __newindex = function() error('readonly') end,
+            })",
+        )
+        .exec()
+        .unwrap();
+
+        let Err(LuauError::Runtime { message, .. }) = luau.get_global::<f64>("missing") else {
+            panic!("Expected the __index error to be returned");
+        };
+        assert!(message.contains("no global"), "{message}");
+
+        let Err(LuauError::Runtime { message, .. }) = luau.set_global("missing", 1.0) else {
+            panic!("Expected the __newindex error to be returned");
+        };
+        assert!(message.contains("readonly"), "{message}");
+
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
     #[test]
     fn userdata_borrow() {
         let luau = Luau::default();
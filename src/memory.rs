@@ -1,10 +1,13 @@
 use std::{
     alloc::{self, Layout},
-    ffi::c_void,
+    ffi::{c_int, c_void},
     ptr::null_mut,
 };
 
-use crate::AssociatedData;
+use crate::{
+    ffi::{luauconf::LUA_MEMORY_CATEGORIES, prelude::*},
+    AssociatedData, Luau,
+};
 
 pub trait LuauAllocator {
     fn allocate(&self, size: usize) -> *mut c_void;
@@ -61,25 +64,15 @@ impl LuauAllocator for DefaultLuauAllocator {
             Err(_) => return null_mut(),
         };
 
-        let new_ptr = unsafe { alloc::alloc(new_layout) as *mut c_void };
-
-        if new_ptr.is_null() {
-            alloc::handle_alloc_error(new_layout);
-        }
-
-        new_ptr
+        // a null pointer is reported to Luau as a memory error
+        unsafe { alloc::alloc(new_layout) as *mut c_void }
     }
 
     fn reallocate(&self, ptr: *mut c_void, old_size: usize, new_size: usize) -> *mut c_void {
         let old_layout = unsafe { Layout::from_size_align_unchecked(old_size, PLATFORM_ALIGNMENT) };
 
-        let new_ptr = unsafe { alloc::realloc(ptr as *mut u8, old_layout, new_size) };
-
-        if new_ptr.is_null() {
-            alloc::handle_alloc_error(old_layout);
-        }
-
-        new_ptr as _
+        // on failure the original block is left untouched as Luau expects
+        unsafe { alloc::realloc(ptr as *mut u8, old_layout, new_size) as _ }
     }
 
     fn deallocate(&self, ptr: *mut c_void, old_size: usize) {
@@ -104,23 +97,218 @@ pub(crate) unsafe extern "C-unwind" fn luau_alloc_cb(
 ) -> *mut c_void {
     let associated_data = ud.cast::<AssociatedData>().as_mut().unwrap();
 
+    // Luau does not pass a meaningful old size for new blocks
+    let old_size = if ptr.is_null() { 0 } else { old_size };
+
     if new_size == 0 {
         if !ptr.is_null() {
             associated_data.allocator.deallocate(ptr, old_size);
+            associated_data.memory_used -= old_size;
         }
 
         return null_mut();
     }
 
-    if new_size > isize::MAX as usize {
-        return null_mut();
-    }
+    let used = (associated_data.memory_used - old_size).saturating_add(new_size);
+
+    let exceeds_limit = associated_data
+        .memory_limit
+        .is_some_and(|limit| new_size > old_size && used > limit);
 
-    if ptr.is_null() {
+    let new_ptr = if new_size > isize::MAX as usize || exceeds_limit {
+        null_mut()
+    } else if ptr.is_null() {
         associated_data.allocator.allocate(new_size)
     } else {
         associated_data
             .allocator
             .reallocate(ptr, old_size, new_size)
+    };
+
+    // a null pointer makes Luau raise a memory error, which is caught at the nearest protected call
+    if new_ptr.is_null() {
+        associated_data.allocation_failed = true;
+    } else {
+        associated_data.memory_used = used;
+    }
+
+    new_ptr
+}
+
+/// Tracks the memory category of threads, which inherit the category of the thread that created them
+pub(crate) unsafe extern "C-unwind" fn userthread_callback(
+    parent: *mut _LuaState,
+    state: *mut _LuaState,
+) {
+    let associated_data = &mut *Luau::from_ptr(state).get_associated_mut();
+
+    if parent.is_null() {
+        associated_data.memory_categories.remove(&state);
+    } else if let Some(&cat) = associated_data.memory_categories.get(&parent) {
+        associated_data.memory_categories.insert(state, cat);
+    }
+}
+
+/// Heap usage of a single memory category, returned by `Luau::memory_by_category`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryCategory {
+    pub index: c_int,
+    pub name: Option<String>,
+    pub bytes: usize,
+}
+
+/// Restores the previous memory category of a thread when dropped, created by `Luau::scoped_memory_category`
+pub struct MemoryCategoryGuard<'a> {
+    luau: &'a Luau,
+    previous: c_int,
+}
+
+impl Drop for MemoryCategoryGuard<'_> {
+    fn drop(&mut self) {
+        self.luau.set_memory_category(self.previous);
+    }
+}
+
+impl Luau {
+    /// Sets the memory category of this thread for all allocations taking place after its set
+    ///
+    /// Threads created afterwards start in the same category.
+    pub fn set_memory_category(&self, cat: c_int) {
+        assert!(
+            (0..LUA_MEMORY_CATEGORIES).contains(&cat),
+            "Memory category index must be below {LUA_MEMORY_CATEGORIES}"
+        );
+
+        // SAFETY: the category is in range and the associated data is only borrowed for the update
+        unsafe {
+            lua_setmemcat(self.state, cat);
+
+            let categories = &mut (*self.get_associated_mut()).memory_categories;
+
+            // the default category is not stored so the map only holds threads which changed it
+            if cat == 0 {
+                categories.remove(&self.state);
+            } else {
+                categories.insert(self.state, cat);
+            }
+        }
+    }
+
+    /// Returns the memory category of this thread
+    pub fn memory_category(&self) -> c_int {
+        self.get_associated()
+            .memory_categories
+            .get(&self.state)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Switches the memory category of this thread until the returned guard is dropped
+    pub fn scoped_memory_category(&self, cat: c_int) -> MemoryCategoryGuard<'_> {
+        let previous = self.memory_category();
+        self.set_memory_category(cat);
+
+        MemoryCategoryGuard {
+            luau: self,
+            previous,
+        }
+    }
+
+    /// Names a memory category for reporting by `memory_by_category`
+    pub fn set_memory_category_name(&self, cat: c_int, name: impl Into<String>) {
+        assert!(
+            (0..LUA_MEMORY_CATEGORIES).contains(&cat),
+            "Memory category index must be below {LUA_MEMORY_CATEGORIES}"
+        );
+
+        // SAFETY: the associated data is only borrowed for the insertion
+        unsafe {
+            (*self.get_associated_mut())
+                .memory_category_names
+                .insert(cat, name.into())
+        };
+    }
+
+    /// Returns the name given to a memory category by `set_memory_category_name`
+    pub fn memory_category_name(&self, cat: c_int) -> Option<String> {
+        self.get_associated()
+            .memory_category_names
+            .get(&cat)
+            .cloned()
+    }
+
+    /// Returns the bytes allocated in a memory category, or in all categories if `cat` is negative
+    pub fn total_bytes(&self, cat: c_int) -> usize {
+        assert!(
+            cat < LUA_MEMORY_CATEGORIES,
+            "Memory category index must be below {LUA_MEMORY_CATEGORIES}"
+        );
+
+        // SAFETY: the category is in range
+        unsafe { lua_totalbytes(self.state, cat) }
+    }
+
+    /// Returns the heap usage of every memory category which currently holds allocations
+    pub fn memory_by_category(&self) -> Vec<MemoryCategory> {
+        let names = &self.get_associated().memory_category_names;
+
+        (0..LUA_MEMORY_CATEGORIES)
+            .filter_map(|index| {
+                let bytes = self.total_bytes(index);
+
+                (bytes > 0).then(|| MemoryCategory {
+                    index,
+                    name: names.get(&index).cloned(),
+                    bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Luau;
+
+    #[test]
+    fn memory_categories() {
+        let luau = Luau::default();
+
+        luau.set_memory_category_name(1, "plugin");
+
+        let thread = {
+            let _guard = luau.scoped_memory_category(1);
+
+            luau.push_string(vec![b'a'; 4096]);
+            luau.pop(1);
+
+            assert_eq!(luau.memory_category(), 1);
+            luau.new_thread()
+        };
+
+        assert_eq!(luau.memory_category(), 0);
+        assert_eq!(
+            thread.get_state().memory_category(),
+            1,
+            "Expected threads to inherit the category of their creator"
+        );
+
+        assert!(luau.total_bytes(1) >= 4096);
+        assert_eq!(
+            luau.total_bytes(-1),
+            luau.memory_by_category()
+                .iter()
+                .map(|c| c.bytes)
+                .sum::<usize>()
+        );
+
+        let plugin = luau
+            .memory_by_category()
+            .into_iter()
+            .find(|c| c.index == 1)
+            .unwrap();
+
+        assert_eq!(plugin.name.as_deref(), Some("plugin"));
+        assert_eq!(luau.memory_category_name(2), None);
     }
 }
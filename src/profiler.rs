@@ -0,0 +1,490 @@
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ffi::{c_int, CStr},
+    fmt::Display,
+    io::{self, Write},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{ffi::prelude::*, interrupt::interrupt_callback, Luau};
+
+/// A function identified by its name and where it was defined
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProfiledFunction {
+    /// Name of the function, None for the main chunk and anonymous functions
+    pub name: Option<String>,
+    /// Chunk name of the function, `=[C]` for Rust and C functions
+    pub source: String,
+    /// Line the function was defined on, 0 for the main chunk and -1 for Rust and C functions
+    pub line_defined: c_int,
+}
+
+impl Display for ProfiledFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = self.source.strip_prefix(['@', '=']).unwrap_or(&self.source);
+
+        match &self.name {
+            Some(name) => write!(f, "{name}")?,
+            None if self.line_defined == 0 => write!(f, "<main>")?,
+            None => write!(f, "<anonymous>")?,
+        }
+
+        if self.line_defined >= 0 {
+            write!(f, " ({source}:{})", self.line_defined)
+        } else {
+            write!(f, " ({source})")
+        }
+    }
+}
+
+/// A call stack and the samples which were taken while it was running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackSamples {
+    /// Functions from the outermost call to the running function
+    pub frames: Vec<ProfiledFunction>,
+    /// Number of samples taken
+    pub samples: u64,
+    /// Time attributed to the samples, the sampling interval for each sample
+    pub time: Duration,
+}
+
+/// Time spent in a single function, see `ProfileReport::functions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionTime {
+    pub function: ProfiledFunction,
+    /// Time spent running the function itself
+    pub self_time: Duration,
+    /// Time spent running the function or functions it called
+    pub total_time: Duration,
+    pub self_samples: u64,
+    pub total_samples: u64,
+}
+
+/// Samples gathered by a `Profiler`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileReport {
+    stacks: Vec<StackSamples>,
+}
+
+impl ProfileReport {
+    /// Returns every sampled call stack
+    pub fn stacks(&self) -> &[StackSamples] {
+        &self.stacks
+    }
+
+    /// Returns the total number of samples
+    pub fn samples(&self) -> u64 {
+        self.stacks.iter().map(|stack| stack.samples).sum()
+    }
+
+    /// Returns the self and total time of every sampled function, the most expensive by self time first
+    pub fn functions(&self) -> Vec<FunctionTime> {
+        let mut functions = HashMap::<&ProfiledFunction, FunctionTime>::new();
+
+        for stack in &self.stacks {
+            let mut seen = HashSet::new();
+
+            for (i, function) in stack.frames.iter().enumerate() {
+                let entry = functions.entry(function).or_insert_with(|| FunctionTime {
+                    function: function.clone(),
+                    self_time: Duration::ZERO,
+                    total_time: Duration::ZERO,
+                    self_samples: 0,
+                    total_samples: 0,
+                });
+
+                if i == stack.frames.len() - 1 {
+                    entry.self_time += stack.time;
+                    entry.self_samples += stack.samples;
+                }
+
+                // recursive calls are only counted once towards the total
+                if seen.insert(function) {
+                    entry.total_time += stack.time;
+                    entry.total_samples += stack.samples;
+                }
+            }
+        }
+
+        let mut functions = functions.into_values().collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            (b.self_time, b.total_time)
+                .cmp(&(a.self_time, a.total_time))
+                .then_with(|| a.function.to_string().cmp(&b.function.to_string()))
+        });
+
+        functions
+    }
+
+    /// Writes the samples as collapsed stacks, one `outer;inner;running microseconds` line per stack
+    ///
+    /// The output can be rendered by flamegraph tools such as `flamegraph.pl` and inferno.
+    pub fn write_collapsed(&self, mut writer: impl Write) -> io::Result<()> {
+        for stack in &self.stacks {
+            let frames = stack
+                .frames
+                .iter()
+                .map(|function| function.to_string().replace(';', ":"))
+                .collect::<Vec<_>>();
+
+            writeln!(writer, "{} {}", frames.join(";"), stack.time.as_micros())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a table of the self and total time of every sampled function
+    pub fn write_table(&self, mut writer: impl Write) -> io::Result<()> {
+        let total = self
+            .stacks
+            .iter()
+            .map(|stack| stack.time)
+            .sum::<Duration>()
+            .max(Duration::from_nanos(1));
+
+        let percent = |time: Duration| time.as_secs_f64() / total.as_secs_f64() * 100.0;
+
+        writeln!(
+            writer,
+            "{:>10} {:>7} {:>10} {:>7}  function",
+            "self ms", "self %", "total ms", "total %"
+        )?;
+
+        for function in self.functions() {
+            writeln!(
+                writer,
+                "{:>10.3} {:>6.2}% {:>10.3} {:>6.2}%  {}",
+                function.self_time.as_secs_f64() * 1000.0,
+                percent(function.self_time),
+                function.total_time.as_secs_f64() * 1000.0,
+                percent(function.total_time),
+                function.function
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sampling state shared between a `Profiler` and the interrupt callback
+pub(crate) struct Sampler {
+    ticks: Arc<AtomicU64>,
+    interval: Duration,
+    data: RefCell<SamplerData>,
+}
+
+struct SamplerData {
+    last_tick: u64,
+    functions: Vec<ProfiledFunction>,
+    function_ids: HashMap<ProfiledFunction, usize>,
+    stacks: HashMap<Vec<usize>, (u64, Duration)>,
+}
+
+impl Sampler {
+    /// Records the call stack of `luau` if the timer ticked since the previous sample
+    pub(crate) fn sample(&self, luau: &Luau) {
+        let tick = self.ticks.load(Ordering::Relaxed);
+
+        let Ok(mut data) = self.data.try_borrow_mut() else {
+            return;
+        };
+
+        if tick == data.last_tick {
+            return;
+        }
+
+        data.last_tick = tick;
+
+        let mut stack = Vec::new();
+
+        // SAFETY: the strings are copied before the debug structure is reused
+        unsafe {
+            let mut ar: LuaDebug = std::mem::zeroed();
+            let mut level = 0;
+
+            while lua_getinfo(luau.state, level, c"sn".as_ptr(), &raw mut ar) != 0 {
+                let function = ProfiledFunction {
+                    name: (!ar.name.is_null())
+                        .then(|| CStr::from_ptr(ar.name).to_string_lossy().into_owned()),
+                    source: CStr::from_ptr(ar.source).to_string_lossy().into_owned(),
+                    line_defined: ar.linedefined,
+                };
+
+                let next_id = data.functions.len();
+                let id = *data.function_ids.entry(function.clone()).or_insert(next_id);
+
+                if id == next_id {
+                    data.functions.push(function);
+                }
+
+                stack.push(id);
+                level += 1;
+            }
+        }
+
+        // frames are walked from the running function outwards
+        stack.reverse();
+
+        let entry = data.stacks.entry(stack).or_default();
+        entry.0 += 1;
+        entry.1 += self.interval;
+    }
+
+    fn report(&self) -> ProfileReport {
+        let data = self.data.borrow();
+
+        let mut stacks = data
+            .stacks
+            .iter()
+            .map(|(stack, &(samples, time))| StackSamples {
+                frames: stack.iter().map(|&id| data.functions[id].clone()).collect(),
+                samples,
+                time,
+            })
+            .collect::<Vec<_>>();
+
+        stacks.sort_by_key(|stack| Reverse(stack.time));
+
+        ProfileReport { stacks }
+    }
+}
+
+/// Sampling profiler which records the running call stack at a fixed interval, created by `Profiler::start`
+///
+/// A timer thread ticks at the sampling interval and the next safepoint reached by a script records its call stack,
+/// so samples are taken from interpreted and natively compiled functions alike without single-stepping.
+/// Each sample is weighted by the sampling interval, ticks missed while no script was running are not counted.
+pub struct Profiler<'lua> {
+    luau: &'lua Luau,
+    sampler: Rc<Sampler>,
+    running: Arc<AtomicBool>,
+    timer: Option<JoinHandle<()>>,
+    previous_interrupt: Option<unsafe extern "C-unwind" fn(*mut _LuaState, c_int)>,
+}
+
+impl<'lua> Profiler<'lua> {
+    /// Starts sampling scripts run by `luau` every `interval`
+    ///
+    /// Only one profiler may run on a state at a time, callbacks set by `set_interrupt` keep running alongside it.
+    pub fn start(luau: &'lua Luau, interval: Duration) -> Self {
+        assert!(
+            luau.get_associated().profiler.is_none(),
+            "Only one profiler may run on a state at a time"
+        );
+        assert!(
+            !interval.is_zero(),
+            "The sampling interval must not be zero"
+        );
+
+        let ticks = Arc::new(AtomicU64::new(0));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let timer = {
+            let ticks = ticks.clone();
+            let running = running.clone();
+
+            // parked rather than sleeping so the profiler can wake the timer when it stops
+            thread::spawn(move || {
+                let mut next = Instant::now() + interval;
+
+                while running.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+
+                    // parking may return early, the tick is only counted once the interval elapsed
+                    if now < next {
+                        thread::park_timeout(next - now);
+                        continue;
+                    }
+
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                    next += interval;
+                }
+            })
+        };
+
+        let sampler = Rc::new(Sampler {
+            ticks,
+            interval,
+            data: RefCell::new(SamplerData {
+                last_tick: 0,
+                functions: Vec::new(),
+                function_ids: HashMap::new(),
+                stacks: HashMap::new(),
+            }),
+        });
+
+        // SAFETY: the state is valid and the associated data is only borrowed for the assignment
+        let previous_interrupt = unsafe {
+            (*luau.get_associated_mut()).profiler = Some(sampler.clone());
+            (*lua_callbacks(luau.state))
+                .interrupt
+                .replace(interrupt_callback)
+        };
+
+        Self {
+            luau,
+            sampler,
+            running,
+            timer: Some(timer),
+            previous_interrupt,
+        }
+    }
+
+    /// Returns the samples gathered so far
+    pub fn report(&self) -> ProfileReport {
+        self.sampler.report()
+    }
+
+    /// Stops sampling and returns the samples gathered
+    pub fn stop(self) -> ProfileReport {
+        self.report()
+    }
+}
+
+impl Drop for Profiler<'_> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        if let Some(timer) = self.timer.take() {
+            timer.thread().unpark();
+            let _ = timer.join();
+        }
+
+        // SAFETY: the state is valid and the associated data is only borrowed for the assignment
+        unsafe {
+            (*self.luau.get_associated_mut()).profiler = None;
+
+            // a callback set by `set_interrupt` while profiling still needs the slot
+            if self.luau.get_associated().interrupt.is_none() {
+                (*lua_callbacks(self.luau.state)).interrupt = self.previous_interrupt;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{ffi::prelude::*, Luau, LuauLibs, Profiler};
+
+    #[test]
+    fn sampling() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let profiler = Profiler::start(&luau, Duration::from_millis(1));
+
+        luau.chunk(
+            "local function hot()
+    local x = 0
+    for i = 1, 10000 do x += i end
+    return x
+end
+local start = os.clock()
+while os.clock() - start < 0.05 do hot() end",
+        )
+        .set_name(c"@profile.luau")
+        .exec()
+        .unwrap();
+
+        let report = profiler.stop();
+
+        assert!(report.samples() > 0, "Expected samples to be taken");
+
+        let hot = report
+            .functions()
+            .into_iter()
+            .find(|function| function.function.name.as_deref() == Some("hot"))
+            .expect("Expected the hot function to be sampled");
+
+        assert!(hot.self_samples > 0);
+        assert!(hot.total_time >= hot.self_time);
+
+        let mut collapsed = Vec::new();
+        report.write_collapsed(&mut collapsed).unwrap();
+        let collapsed = String::from_utf8(collapsed).unwrap();
+
+        assert!(
+            collapsed
+                .lines()
+                .any(|line| line.starts_with("<main> (profile.luau:0);hot (profile.luau:1) ")),
+            "Unexpected collapsed stacks {collapsed}"
+        );
+
+        let mut table = Vec::new();
+        report.write_table(&mut table).unwrap();
+
+        assert!(String::from_utf8(table)
+            .unwrap()
+            .contains("hot (profile.luau:1)"));
+    }
+
+    #[test]
+    fn stopping() {
+        let luau = Luau::default();
+
+        let profiler = Profiler::start(&luau, Duration::from_secs(60));
+        let start = Instant::now();
+        drop(profiler);
+
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "Expected the timer to stop without waiting for the interval"
+        );
+
+        // SAFETY: the state is valid
+        assert!(
+            unsafe { (*lua_callbacks(luau.to_ptr())).interrupt }.is_none(),
+            "Expected the interrupt callback to be restored"
+        );
+    }
+
+    #[cfg(feature = "codegen")]
+    #[test]
+    fn codegen() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.enable_codegen();
+
+        let function = luau
+            .chunk(
+                "local function hot()
+    local x = 0
+    for i = 1, 10000 do x += i end
+    return x
+end
+local start = os.clock()
+while os.clock() - start < 0.05 do hot() end",
+            )
+            .set_name(c"@native.luau")
+            .into_function()
+            .unwrap();
+
+        luau.push(&function);
+        luau.codegen(-1);
+        luau.pop(1);
+
+        let profiler = Profiler::start(&luau, Duration::from_millis(1));
+        function.call::<_, ()>(()).unwrap();
+        let report = profiler.stop();
+
+        assert!(
+            report
+                .functions()
+                .iter()
+                .any(|function| function.function.name.as_deref() == Some("hot")),
+            "Expected natively compiled functions to be sampled"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    cell::Cell,
+    ffi::c_int,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    conversion::{FromLuau, FromLuauMulti, IntoLuau, IntoLuauMulti},
+    ffi::prelude::*,
+    ConversionError, Luau, LuauError,
+};
+
+/// Owned reference to a Luau value which is kept alive in the registry until dropped
+pub struct LuauRef {
+    main_state: *mut _LuaState,
+    root_check: Rc<Cell<bool>>,
+    index: RefIndex,
+}
+
+impl LuauRef {
+    /// Creates a reference to the value at `idx`
+    pub(crate) fn new(luau: &Luau, idx: c_int) -> Self {
+        Self {
+            // SAFETY: the state is valid for the lifetime of the Luau struct
+            main_state: unsafe { lua_mainthread(luau.to_ptr()) },
+            root_check: luau.get_associated().main_thread_rc.clone(),
+            index: luau.reference(idx),
+        }
+    }
+
+    /// Returns the main state the reference belongs to
+    fn state(&self) -> Luau {
+        assert!(
+            self.root_check.get(),
+            "Attempted to use a Luau reference when the main state has died"
+        );
+
+        // SAFETY: the main state is alive as checked above
+        unsafe { Luau::from_ptr(self.main_state) }
+    }
+
+    /// Pushes the referenced value onto the stack of `luau`, which must share this reference's main state
+    fn push_ref(&self, luau: &Luau) {
+        assert!(
+            self.root_check.get(),
+            "Attempted to use a Luau reference when the main state has died"
+        );
+
+        // SAFETY: luau is a valid state
+        assert!(
+            unsafe { lua_mainthread(luau.to_ptr()) } == self.main_state,
+            "Luau references may only be used with the state they were created from"
+        );
+
+        luau.get_reference(self.index);
+    }
+
+    /// Returns the type of the referenced value
+    pub fn type_of(&self) -> LuauType {
+        let luau = self.state();
+
+        let ty = luau.get_reference(self.index);
+        luau.pop(1);
+
+        ty
+    }
+
+    /// Converts the referenced value into a Rust value
+    pub fn get<T: FromLuau>(&self) -> Result<T, ConversionError> {
+        let luau = self.state();
+
+        luau.get_reference(self.index);
+        let value = T::from_luau(&luau, luau.top());
+        luau.pop(1);
+
+        value
+    }
+}
+
+impl Clone for LuauRef {
+    fn clone(&self) -> Self {
+        let luau = self.state();
+
+        luau.get_reference(self.index);
+        let cloned = LuauRef::new(&luau, -1);
+        luau.pop(1);
+
+        cloned
+    }
+}
+
+impl Debug for LuauRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LuauRef").field(&self.index).finish()
+    }
+}
+
+impl Drop for LuauRef {
+    fn drop(&mut self) {
+        // the registry is freed alongside the state
+        if self.root_check.get() {
+            // SAFETY: the main state is alive as checked above
+            unsafe { lua_unref(self.main_state, self.index) }
+        }
+    }
+}
+
+impl IntoLuau for LuauRef {
+    fn push_to(self, luau: &Luau) {
+        self.push_ref(luau);
+    }
+}
+
+impl IntoLuau for &LuauRef {
+    fn push_to(self, luau: &Luau) {
+        self.push_ref(luau);
+    }
+}
+
+impl FromLuau for LuauRef {
+    fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+        match luau.type_of_or_none(idx) {
+            LuauType::LUA_TNONE => Err(ConversionError::new(idx, LuauType::LUA_TNONE, "value")),
+            _ => Ok(LuauRef::new(luau, idx)),
+        }
+    }
+}
+
+macro_rules! typed_ref {
+    ($(#[$meta:meta])* $name:ident, $ty:path, $expected:literal) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug)]
+        pub struct $name(LuauRef);
+
+        impl $name {
+            /// Converts the handle into an untyped reference
+            pub fn into_ref(self) -> LuauRef {
+                self.0
+            }
+        }
+
+        impl IntoLuau for $name {
+            fn push_to(self, luau: &Luau) {
+                self.0.push_ref(luau);
+            }
+        }
+
+        impl IntoLuau for &$name {
+            fn push_to(self, luau: &Luau) {
+                self.0.push_ref(luau);
+            }
+        }
+
+        impl FromLuau for $name {
+            fn from_luau(luau: &Luau, idx: c_int) -> Result<Self, ConversionError> {
+                match luau.type_of_or_none(idx) {
+                    $ty => Ok($name(LuauRef::new(luau, idx))),
+                    found => Err(ConversionError::new(idx, found, $expected)),
+                }
+            }
+        }
+    };
+}
+
+typed_ref!(
+    /// Owned reference to a Luau table
+    LuauTable,
+    LuauType::LUA_TTABLE,
+    "table"
+);
+
+typed_ref!(
+    /// Owned reference to a Luau function
+    LuauFunction,
+    LuauType::LUA_TFUNCTION,
+    "function"
+);
+
+typed_ref!(
+    /// Owned reference to a Luau string
+    LuauString,
+    LuauType::LUA_TSTRING,
+    "string"
+);
+
+unsafe extern "C-unwind" fn protected_get_table(state: *mut _LuaState) -> c_int {
+    lua_gettable(state, 1);
+    1
+}
+
+unsafe extern "C-unwind" fn protected_set_table(state: *mut _LuaState) -> c_int {
+    lua_settable(state, 1);
+    0
+}
+
+unsafe extern "C-unwind" fn protected_raw_set_table(state: *mut _LuaState) -> c_int {
+    lua_rawset(state, 1);
+    0
+}
+
+impl LuauTable {
+    /// Calls `func` in protected mode with the table followed by `args`, reading the results at the top of the stack
+    fn protected<A: IntoLuauMulti, R: FromLuauMulti>(
+        &self,
+        func: CFunction,
+        args: A,
+        nresults: c_int,
+    ) -> Result<R, LuauError> {
+        let luau = self.0.state();
+        let top = luau.top();
+
+        // SAFETY: the trampolines only operate on the arguments they are called with
+        unsafe { luau.push_raw_function(func, None, 0, None) };
+        self.0.push_ref(&luau);
+        let nargs = luau.push(args);
+
+        luau.call(nargs + 1, nresults)?;
+
+        let results = R::from_luau_multi(&luau, top + 1);
+        luau.pop(luau.top() - top);
+
+        results.map_err(LuauError::Conversion)
+    }
+
+    /// Returns t\[key\], may invoke the __index metamethod
+    pub fn get<K: IntoLuau, V: FromLuau>(&self, key: K) -> Result<V, LuauError> {
+        self.protected(protected_get_table, key, 1)
+    }
+
+    /// Sets t\[key\] = value, may invoke the __newindex metamethod
+    pub fn set<K: IntoLuau, V: IntoLuau>(&self, key: K, value: V) -> Result<(), LuauError> {
+        self.protected(protected_set_table, (key, value), 0)
+    }
+
+    /// Returns t\[key\] without invoking metamethods
+    pub fn raw_get<K: IntoLuau, V: FromLuau>(&self, key: K) -> Result<V, ConversionError> {
+        let luau = self.0.state();
+        let top = luau.top();
+
+        self.0.push_ref(&luau);
+        key.push_to(&luau);
+
+        // SAFETY: the table and key were pushed above
+        unsafe { lua_rawget(luau.to_ptr(), -2) };
+
+        let value = V::from_luau(&luau, luau.top());
+        luau.pop(luau.top() - top);
+
+        value
+    }
+
+    /// Sets t\[key\] = value without invoking metamethods
+    ///
+    /// Errors if the key is nil or NaN or if the table is readonly.
+    pub fn raw_set<K: IntoLuau, V: IntoLuau>(&self, key: K, value: V) -> Result<(), LuauError> {
+        let luau = self.0.state();
+
+        self.0.push_ref(&luau);
+        // SAFETY: the table was pushed above
+        let readonly = unsafe { lua_getreadonly(luau.to_ptr(), -1) } != 0;
+        luau.pop(1);
+
+        if readonly {
+            return Err(LuauError::Runtime {
+                message: "attempt to modify a readonly table".to_string(),
+                traceback: None,
+            });
+        }
+
+        self.protected(protected_raw_set_table, (key, value), 0)
+    }
+
+    /// Returns the length of the table's array part without invoking the __len metamethod
+    pub fn len(&self) -> usize {
+        let luau = self.0.state();
+
+        self.0.push_ref(&luau);
+        // SAFETY: the table was pushed above
+        let len = unsafe { lua_objlen(luau.to_ptr(), -1) };
+        luau.pop(1);
+
+        len as usize
+    }
+
+    /// Returns true if the table's array part is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl LuauFunction {
+    /// Calls the function with `args` in protected mode and converts its results
+    pub fn call<A: IntoLuauMulti, R: FromLuauMulti>(&self, args: A) -> Result<R, LuauError> {
+        let luau = self.0.state();
+        let top = luau.top();
+
+        self.0.push_ref(&luau);
+        let nargs = luau.push(args);
+
+        luau.call(nargs, LUA_MULTRET)?;
+
+        let results = R::from_luau_multi(&luau, top + 1);
+        luau.pop(luau.top() - top);
+
+        results.map_err(LuauError::Conversion)
+    }
+}
+
+impl LuauString {
+    /// Copies the bytes of the string
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let luau = self.0.state();
+
+        self.0.push_ref(&luau);
+        let bytes = luau
+            .to_str_slice(-1)
+            .expect("Expected referenced value to be a string")
+            .to_vec();
+        luau.pop(1);
+
+        bytes
+    }
+
+    /// Copies the string, replacing invalid UTF-8 sequences with the replacement character
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.to_bytes()).into_owned()
+    }
+}
+
+impl Display for LuauString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Luau, LuauError, LuauFunction, LuauString, LuauTable};
+
+    #[test]
+    fn tables() {
+        let luau = Luau::default();
+
+        luau.create_table();
+        let table = luau.get::<LuauTable>(-1).unwrap();
+        luau.pop(1);
+
+        table.set(1, "a").unwrap();
+        table.set("key", 2.0).unwrap();
+        table.raw_set(2, "b").unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get::<_, String>(1).unwrap(), "a");
+        assert_eq!(table.raw_get::<_, f64>("key"), Ok(2.0));
+        assert!(matches!(
+            table.get::<_, f64>(1),
+            Err(LuauError::Conversion(_))
+        ));
+        assert!(
+            table.raw_set(None::<f64>, 1).is_err(),
+            "Expected a nil key to error"
+        );
+
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn functions_and_strings() {
+        let luau = Luau::default();
+
+        luau.create_function(|_, (a, b): (f64, f64)| Ok::<_, LuauError>(a + b));
+        let function = luau.get::<LuauFunction>(-1).unwrap();
+
+        luau.push("hello");
+        let string = luau.get::<LuauString>(-1).unwrap();
+        luau.pop(2);
+
+        assert_eq!(function.call::<_, f64>((1.0, 2.0)).unwrap(), 3.0);
+        assert!(function.call::<_, f64>("a").is_err());
+        assert_eq!(string.to_string_lossy(), "hello");
+
+        let cloned = string.clone();
+        drop(string);
+
+        assert_eq!(cloned.to_bytes(), b"hello");
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn outlives_state() {
+        let table = {
+            let luau = Luau::default();
+
+            luau.create_table();
+            luau.get::<LuauTable>(-1).unwrap()
+        };
+
+        // dropping after the state is closed must not touch the freed registry
+        drop(table);
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    ffi::{CStr, CString},
+    fmt::Display,
+    fs, io,
+    path::PathBuf,
+    rc::Rc,
+};
+
+use crate::{compile::Compiler, ffi::prelude::*, Luau, LuauError, LuauRef};
+
+/// Source of a module found by a `ModuleResolver`
+#[derive(Debug, Clone)]
+pub struct ModuleSource {
+    /// Path like name of the module such as `lib/utils.luau`, used as its chunk name and to resolve relative requires
+    pub name: String,
+    pub source: Vec<u8>,
+}
+
+/// Finds the source of modules loaded by `require`
+pub trait ModuleResolver {
+    /// Finds the module at `path`, a normalized path relative to the resolver's root such as `lib/utils`
+    ///
+    /// Returns None if the module does not exist.
+    fn find(&self, path: &str) -> io::Result<Option<ModuleSource>>;
+}
+
+/// Looks up `path` followed by `path/init`
+fn find_with_init(
+    path: &str,
+    mut lookup: impl FnMut(&str) -> io::Result<Option<ModuleSource>>,
+) -> io::Result<Option<ModuleSource>> {
+    match lookup(path)? {
+        Some(module) => Ok(Some(module)),
+        None => lookup(&format!("{path}/init")),
+    }
+}
+
+/// Resolves modules to `.luau` or `.lua` files, or `init` files in directories, below a root directory
+pub struct FileSystemResolver {
+    root: PathBuf,
+}
+
+impl FileSystemResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ModuleResolver for FileSystemResolver {
+    fn find(&self, path: &str) -> io::Result<Option<ModuleSource>> {
+        find_with_init(path, |path| {
+            for extension in ["luau", "lua"] {
+                let name = format!("{path}.{extension}");
+
+                match fs::read(self.root.join(&name)) {
+                    Ok(source) => return Ok(Some(ModuleSource { name, source })),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            Ok(None)
+        })
+    }
+}
+
+/// Resolves modules from sources added at runtime, keyed by their path without an extension
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    modules: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a module, returning the source previously stored at `path`
+    pub fn insert(
+        &mut self,
+        path: impl Into<String>,
+        source: impl Into<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        self.modules.insert(path.into(), source.into())
+    }
+}
+
+impl ModuleResolver for MemoryResolver {
+    fn find(&self, path: &str) -> io::Result<Option<ModuleSource>> {
+        find_with_init(path, |path| {
+            Ok(self.modules.get(path).map(|source| ModuleSource {
+                name: path.to_string(),
+                source: source.clone(),
+            }))
+        })
+    }
+}
+
+/// Resolves modules from sources compiled into the binary, keyed by their path without an extension
+///
+/// ```ignore
+/// const MODULES: EmbeddedResolver = EmbeddedResolver::new(&[
+///     ("lib/utils", include_bytes!("lib/utils.luau")),
+/// ]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedResolver {
+    modules: &'static [(&'static str, &'static [u8])],
+}
+
+impl EmbeddedResolver {
+    pub const fn new(modules: &'static [(&'static str, &'static [u8])]) -> Self {
+        Self { modules }
+    }
+}
+
+impl ModuleResolver for EmbeddedResolver {
+    fn find(&self, path: &str) -> io::Result<Option<ModuleSource>> {
+        find_with_init(path, |path| {
+            Ok(self
+                .modules
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(name, source)| ModuleSource {
+                    name: name.to_string(),
+                    source: source.to_vec(),
+                }))
+        })
+    }
+}
+
+/// Error raised by `require`
+#[derive(Debug)]
+pub enum RequireError {
+    /// The path does not start with `./`, `../` or `@`, or leaves the resolver's root
+    InvalidPath(String),
+    /// The path starts with an alias which was not added by `Luau::set_require_alias`
+    UnknownAlias(String),
+    /// The resolver did not find a module at the normalized path
+    NotFound(String),
+    /// The resolver failed to read the module at the normalized path
+    Io { path: String, source: io::Error },
+    /// A module required itself through the chain of module names
+    Cycle(Vec<String>),
+    /// The module failed to compile
+    Syntax { name: String, message: String },
+    /// The module raised an error while running
+    Module { name: String, error: LuauError },
+    /// The module did not return exactly one value
+    ReturnCount { name: String, count: i32 },
+}
+
+impl Error for RequireError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RequireError::Io { source, .. } => Some(source),
+            RequireError::Module { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RequireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequireError::InvalidPath(path) => write!(
+                f,
+                "invalid require path '{path}', paths must start with './', '../' or '@' and stay within the root"
+            ),
+            RequireError::UnknownAlias(alias) => write!(f, "unknown require alias '@{alias}'"),
+            RequireError::NotFound(path) => write!(f, "module '{path}' not found"),
+            RequireError::Io { path, source } => write!(f, "failed to read module '{path}': {source}"),
+            RequireError::Cycle(chain) => write!(f, "cyclic require: {}", chain.join(" -> ")),
+            RequireError::Syntax { name, message } => {
+                write!(f, "failed to compile module '{name}': {message}")
+            }
+            RequireError::Module { name, error } => {
+                write!(f, "error while loading module '{name}': {error}")
+            }
+            RequireError::ReturnCount { name, count } => write!(
+                f,
+                "module '{name}' must return exactly one value, returned {count}"
+            ),
+        }
+    }
+}
+
+pub(crate) struct Require {
+    resolver: Box<dyn ModuleResolver>,
+    aliases: RefCell<HashMap<String, String>>,
+    /// Modules by name, which may be reached through several paths
+    cache: RefCell<HashMap<String, LuauRef>>,
+    paths: RefCell<HashMap<String, String>>,
+    /// Names of the modules which are currently running, outermost first
+    loading: RefCell<Vec<String>>,
+}
+
+/// Removes a module from the loading chain when it finishes, including by unwinding
+struct LoadingGuard<'a>(&'a RefCell<Vec<String>>);
+
+impl Drop for LoadingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().pop();
+    }
+}
+
+/// Joins `path` onto `base` and resolves `.` and `..` components, returning None if the result leaves the root
+fn normalize(base: &str, path: &str) -> Option<String> {
+    let mut parts = base
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+impl Require {
+    /// Returns the directory of the calling chunk if it was named by `require` or with a leading `@`
+    fn caller_directory(luau: &Luau) -> String {
+        // SAFETY: level 1 is the caller of require and the source is copied before returning
+        let source = unsafe {
+            let mut ar: LuaDebug = std::mem::zeroed();
+
+            if lua_getinfo(luau.state, 1, c"s".as_ptr(), &raw mut ar) == 0 || ar.source.is_null() {
+                return String::new();
+            }
+
+            CStr::from_ptr(ar.source).to_string_lossy().into_owned()
+        };
+
+        match source.strip_prefix('@') {
+            Some(name) => name
+                .rsplit_once('/')
+                .map(|(directory, _)| directory.to_string())
+                .unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    fn resolve(&self, luau: &Luau, path: &str) -> Result<String, RequireError> {
+        let invalid = || RequireError::InvalidPath(path.to_string());
+
+        if let Some(aliased) = path.strip_prefix('@') {
+            let (alias, rest) = aliased.split_once('/').unwrap_or((aliased, ""));
+
+            let aliases = self.aliases.borrow();
+            let base = aliases
+                .get(alias)
+                .ok_or_else(|| RequireError::UnknownAlias(alias.to_string()))?;
+
+            normalize(base, rest).ok_or_else(invalid)
+        } else if path.starts_with("./") || path.starts_with("../") {
+            normalize(&Self::caller_directory(luau), path).ok_or_else(invalid)
+        } else {
+            Err(invalid())
+        }
+    }
+
+    fn require(&self, luau: &Luau, path: &str) -> Result<LuauRef, RequireError> {
+        let path = self.resolve(luau, path)?;
+
+        if let Some(name) = self.paths.borrow().get(&path) {
+            if let Some(module) = self.cache.borrow().get(name) {
+                return Ok(module.clone());
+            }
+        }
+
+        let ModuleSource { name, source } = self
+            .resolver
+            .find(&path)
+            .map_err(|source| RequireError::Io {
+                path: path.clone(),
+                source,
+            })?
+            .ok_or_else(|| RequireError::NotFound(path.clone()))?;
+
+        self.paths.borrow_mut().insert(path, name.clone());
+
+        if let Some(module) = self.cache.borrow().get(&name) {
+            return Ok(module.clone());
+        }
+
+        if let Some(start) = self.loading.borrow().iter().position(|n| *n == name) {
+            let mut chain = self.loading.borrow()[start..].to_vec();
+            chain.push(name);
+
+            return Err(RequireError::Cycle(chain));
+        }
+
+        let result = Compiler::new().compile(&source);
+        let Some(bytecode) = result.bytecode() else {
+            return Err(RequireError::Syntax {
+                message: result.error().unwrap_or_default().to_string(),
+                name,
+            });
+        };
+
+        let chunk_name = CString::new(format!("@{name}"))
+            .map_err(|_| RequireError::InvalidPath(name.clone()))?;
+
+        let top = luau.top();
+
+        luau.load(Some(&chunk_name), bytecode, 0)
+            .map_err(|error| RequireError::Module {
+                name: name.clone(),
+                error,
+            })?;
+
+        let called = {
+            self.loading.borrow_mut().push(name.clone());
+            let _guard = LoadingGuard(&self.loading);
+
+            luau.call(0, LUA_MULTRET)
+        };
+
+        if let Err(error) = called {
+            return Err(RequireError::Module { name, error });
+        }
+
+        let count = luau.top() - top;
+
+        if count != 1 {
+            luau.pop(count);
+            return Err(RequireError::ReturnCount { name, count });
+        }
+
+        let module = LuauRef::new(luau, -1);
+        luau.pop(1);
+
+        self.cache.borrow_mut().insert(name, module.clone());
+
+        Ok(module)
+    }
+
+    fn get(luau: &Luau) -> Option<Rc<Require>> {
+        luau.get_associated().require.clone()
+    }
+}
+
+impl Luau {
+    /// Sets the global `require` function which loads modules found by `resolver`
+    ///
+    /// Paths are relative to the requiring module with a leading `./` or `../`, or to an alias with a leading `@`.
+    /// Modules run once in the environment of the thread which first requires them and their result is cached,
+    /// they are named by their resolved path in error messages and tracebacks.
+    /// This should be called before `sandbox` as it assigns a global.
+    pub fn set_module_resolver(&self, resolver: impl ModuleResolver + 'static) {
+        let require = Rc::new(Require {
+            resolver: Box::new(resolver),
+            aliases: RefCell::default(),
+            cache: RefCell::default(),
+            paths: RefCell::default(),
+            loading: RefCell::default(),
+        });
+
+        // SAFETY: the associated data is only borrowed for the assignment
+        unsafe { (*self.get_associated_mut()).require = Some(require) };
+
+        self.create_function(|luau, path: String| {
+            let require = Require::get(luau).expect("Expected a module resolver to be set");
+
+            require.require(luau, &path)
+        });
+
+        // SAFETY: the function was pushed above
+        unsafe { lua_setglobal(self.state, c"require".as_ptr()) };
+    }
+
+    /// Makes `@alias/...` paths resolve to `path/...`, where `path` is relative to the resolver's root
+    pub fn set_require_alias(&self, alias: impl Into<String>, path: impl AsRef<str>) {
+        let require = Require::get(self).expect("Expected a module resolver to be set");
+        let path = normalize("", path.as_ref()).unwrap_or_default();
+
+        require.aliases.borrow_mut().insert(alias.into(), path);
+    }
+
+    /// Forgets all loaded modules so they run again when next required
+    pub fn clear_require_cache(&self) {
+        if let Some(require) = Require::get(self) {
+            require.cache.borrow_mut().clear();
+            require.paths.borrow_mut().clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        compile::Compiler, FileSystemResolver, Luau, LuauError, LuauLibs, MemoryResolver,
+        RequireError,
+    };
+
+    fn run(luau: &Luau, source: &str) -> Result<(), LuauError> {
+        let result = Compiler::new().compile(source);
+
+        luau.load(Some(c"@main"), result.bytecode().unwrap(), 0)?;
+        luau.call(0, 1)
+    }
+
+    fn resolver(modules: &[(&str, &str)]) -> MemoryResolver {
+        let mut resolver = MemoryResolver::new();
+
+        for (path, source) in modules {
+            resolver.insert(*path, *source);
+        }
+
+        resolver
+    }
+
+    #[test]
+    fn relative_and_aliased() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.set_module_resolver(resolver(&[
+            ("lib/init", "return { value = require('./util').value }"),
+            (
+                "lib/util",
+                "loads = (loads or 0) + 1 return { value = require('../shared').value }",
+            ),
+            ("shared", "return { value = 42 }"),
+        ]));
+        luau.set_require_alias("lib", "lib");
+
+        assert!(run(
+            &luau,
+            "return require('./lib').value + require('@lib/util').value + require('./lib/util').value"
+        )
+        .is_ok());
+        assert_eq!(luau.get::<f64>(-1), Ok(126.0));
+        assert_eq!(
+            luau.get_global::<f64>("loads").unwrap(),
+            1.0,
+            "Expected modules to be cached"
+        );
+
+        luau.clear_require_cache();
+        assert!(run(&luau, "return require('./lib/util')").is_ok());
+        assert_eq!(luau.get_global::<f64>("loads").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn errors() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.set_module_resolver(resolver(&[
+            ("a", "return require('./b')"),
+            ("b", "return require('./a')"),
+            ("bad", "error('boom')"),
+            ("c", "return require('./bad')"),
+            ("none", "return"),
+        ]));
+
+        let require_error = |source: &str| match run(&luau, source) {
+            Err(LuauError::External(err)) => *err.downcast::<RequireError>().unwrap(),
+            other => panic!("Expected a require error, got {other:?}"),
+        };
+
+        assert!(matches!(
+            require_error("return require('missing')"),
+            RequireError::InvalidPath(_)
+        ));
+        assert!(matches!(
+            require_error("return require('./missing')"),
+            RequireError::NotFound(path) if path == "missing"
+        ));
+        assert!(matches!(
+            require_error("return require('../a')"),
+            RequireError::InvalidPath(_)
+        ));
+        assert!(matches!(
+            require_error("return require('@unknown/a')"),
+            RequireError::UnknownAlias(alias) if alias == "unknown"
+        ));
+        assert!(matches!(
+            require_error("return require('./none')"),
+            RequireError::ReturnCount { count: 0, .. }
+        ));
+
+        let err = require_error("return require('./bad')");
+        assert!(
+            err.to_string().contains("bad:1: boom"),
+            "Expected the chunk to be named by its path, got {err}"
+        );
+
+        let err = require_error("return require('./a')");
+        assert!(
+            err.to_string().contains("cyclic require: a -> b -> a"),
+            "Expected the cycle to be reported, got {err}"
+        );
+
+        // the loading chain is unwound after errors so modules which failed can be required again
+        for source in ["return require('./bad')", "return require('./c')"] {
+            let err = require_error(source);
+            assert!(
+                matches!(err, RequireError::Module { .. }) && !err.to_string().contains("cyclic"),
+                "Expected the module to be loaded again, got {err}"
+            );
+            assert!(err.to_string().contains("bad:1: boom"));
+        }
+
+        let err = require_error("return require('./a')");
+        assert!(
+            err.to_string().contains("cyclic require: a -> b -> a"),
+            "Expected the same cycle to be reported, got {err}"
+        );
+    }
+
+    #[test]
+    fn file_system() {
+        let root = std::env::temp_dir().join(format!("rs-luau-require-{}", std::process::id()));
+
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/init.luau"), "return require('./value')").unwrap();
+        fs::write(root.join("pkg/value.lua"), "return 7").unwrap();
+
+        let luau = Luau::default();
+
+        luau.set_module_resolver(FileSystemResolver::new(&root));
+
+        let result = run(&luau, "return require('./pkg')");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(luau.get::<f64>(-1), Ok(7.0));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{ffi::prelude::*, Luau, LuauThread};
+
+impl Luau {
+    /// Makes the global table, the libraries stored in it and the string metatable readonly
+    ///
+    /// This should be called after all libraries and globals are registered and before untrusted code is loaded,
+    /// scripts should then run on threads isolated by `LuauThread::sandbox`.
+    pub fn sandbox(&self) {
+        luau_stack_precondition!(self.check_stack(2));
+
+        // SAFETY: the stack size is checked by the precondition and traversal does not mutate the keys of the table
+        unsafe {
+            lua_pushnil(self.state);
+
+            while lua_next(self.state, LUA_GLOBALSINDEX) != 0 {
+                if lua_type(self.state, -1) == LuauType::LUA_TTABLE {
+                    lua_setreadonly(self.state, -1, 1);
+                }
+
+                lua_pop(self.state, 1);
+            }
+        }
+
+        self.push_string("");
+
+        // SAFETY: the string is on the top of the stack
+        unsafe {
+            if lua_getmetatable(self.state, -1) != 0 {
+                lua_setreadonly(self.state, -1, 1);
+                lua_pop(self.state, 2);
+            } else {
+                lua_pop(self.state, 1);
+            }
+
+            // the environment can no longer change so builtins may be optimized
+            lua_setreadonly(self.state, LUA_GLOBALSINDEX, 1);
+            lua_setsafeenv(self.state, LUA_GLOBALSINDEX, 1);
+        }
+    }
+}
+
+impl LuauThread<'_> {
+    /// Gives the thread its own writable global table which falls back to the globals it shared before
+    ///
+    /// Globals assigned by code loaded into the thread are only visible to that thread,
+    /// the shared globals should be frozen first with `Luau::sandbox`.
+    pub fn sandbox(&self) {
+        let luau = self.get_state();
+
+        luau_stack_precondition!(luau.check_stack(4));
+
+        // SAFETY: the stack size is checked by the precondition
+        unsafe { lua_pushvalue(luau.state, LUA_GLOBALSINDEX) };
+
+        // the globals are copied beforehand as the pseudo index refers to the running function's environment
+        luau.protect(1, 1, |state| unsafe {
+            lua_createtable(state, 0, 0);
+            lua_createtable(state, 0, 1);
+            lua_pushvalue(state, -3);
+            lua_setfield(state, -2, c"__index".as_ptr());
+            lua_setreadonly(state, -1, 1);
+            lua_setmetatable(state, -2);
+            lua_remove(state, -2);
+        });
+
+        // SAFETY: the new environment is on the top of the stack
+        unsafe {
+            // code loaded into the thread afterwards sees an environment which only it mutates
+            lua_replace(luau.state, LUA_GLOBALSINDEX);
+            lua_setsafeenv(luau.state, LUA_GLOBALSINDEX, 1);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use crate::{compile::Compiler, Luau, LuauLibs};
+
+    #[test]
+    fn sandboxed_threads() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+        luau.set_global("shared", 1).unwrap();
+        luau.sandbox();
+
+        let bytecode = Compiler::new()
+            .compile("value = (value or shared) + 1 return value, pcall(function() string.len = nil end)")
+            .bytecode()
+            .unwrap()
+            .to_vec();
+
+        for _ in 0..2 {
+            let thread = luau.new_thread();
+            thread.sandbox();
+
+            let state = thread.get_state();
+
+            state.load(None, &bytecode, 0).unwrap();
+            assert!(state.call(0, 2).is_ok());
+            assert_eq!(
+                state.get::<(f64, bool)>(1),
+                Ok((2.0, false)),
+                "Expected each thread to see its own globals and frozen libraries"
+            );
+        }
+
+        assert_eq!(luau.get_global::<Option<f64>>("value").unwrap(), None);
+        assert!(luau.load(None, &bytecode, 0).is_ok());
+        assert!(
+            luau.call(0, 0).is_err(),
+            "Expected assigning a frozen global to error"
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use std::{cell::Cell, error::Error, fmt::Display, marker::PhantomData, rc::Rc};
 
-use crate::{Luau, _LuaState};
+use crate::{
+    conversion::{FromLuauMulti, IntoLuauMulti},
+    ffi::prelude::*,
+    Luau, LuauError,
+};
 
 pub struct LuauThread<'lua> {
     root_check: Rc<Cell<bool>>,
@@ -47,3 +51,218 @@ impl LuauThread<'_> {
         self.try_get_state().unwrap()
     }
 }
+
+/// Outcome of resuming a thread with `LuauThread::resume`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResumeResult<R> {
+    /// The thread yielded these values and may be resumed again
+    Yielded(R),
+    /// The thread returned these values and has finished
+    Returned(R),
+    /// The thread stopped on a breakpoint and may be resumed again
+    Break,
+}
+
+impl<'lua> LuauThread<'lua> {
+    /// Returns the main state of the thread which resumes it
+    fn main_state(&self) -> Luau {
+        // SAFETY: the thread state is valid as checked by get_state
+        unsafe { Luau::from_ptr(lua_mainthread(self.get_state().to_ptr())) }
+    }
+
+    /// Resumes the thread with `args`, passed to the function on its stack when it is first resumed
+    ///
+    /// Arguments and results are moved through the main state so no values are left on the thread's stack.
+    pub fn resume<A: IntoLuauMulti, R: FromLuauMulti>(
+        &self,
+        args: A,
+    ) -> Result<ResumeResult<R>, LuauError> {
+        let thread = self.get_state();
+        let from = self.main_state();
+        let top = from.top();
+
+        let nargs = from.push(args);
+        luau_stack_precondition!(thread.check_stack(nargs));
+
+        // SAFETY: both states share a main state and the destination stack was checked above
+        unsafe { lua_xmove(from.to_ptr(), thread.to_ptr(), nargs) };
+
+        let status = from.resume(self, nargs)?;
+
+        // a thread stopped on a breakpoint has no results and its frame must stay in place
+        if let LuauStatus::LUA_BREAK = status {
+            return Ok(ResumeResult::Break);
+        }
+
+        let nresults = thread.top();
+        luau_stack_precondition!(from.check_stack(nresults));
+
+        // SAFETY: as above
+        unsafe { lua_xmove(thread.to_ptr(), from.to_ptr(), nresults) };
+
+        let results = R::from_luau_multi(&from, top + 1);
+        from.pop(from.top() - top);
+
+        let results = results.map_err(LuauError::Conversion)?;
+
+        Ok(match status {
+            LuauStatus::LUA_OK => ResumeResult::Returned(results),
+            _ => ResumeResult::Yielded(results),
+        })
+    }
+
+    /// Returns the status of the thread as seen by its main state
+    pub fn status(&self) -> CoroutineStatus {
+        // SAFETY: both states are valid and share a main state
+        unsafe { lua_costatus(self.main_state().to_ptr(), self.get_state().to_ptr()) }
+    }
+
+    /// Returns true if the thread returned or errored, or was reset and has no function to run
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status(),
+            CoroutineStatus::LUA_COFIN | CoroutineStatus::LUA_COERR
+        )
+    }
+
+    /// Returns true if the thread was reset and nothing was pushed onto its stack since
+    pub fn is_reset(&self) -> bool {
+        // SAFETY: the thread state is valid
+        unsafe { lua_isthreadreset(self.get_state().to_ptr()) != 0 }
+    }
+
+    /// Clears the stack and call frames of the thread so it can run a new function
+    pub fn reset(&self) {
+        assert!(
+            !matches!(
+                self.status(),
+                CoroutineStatus::LUA_CORUN | CoroutineStatus::LUA_CONOR
+            ),
+            "A thread must not be reset while it is running"
+        );
+
+        // SAFETY: the thread is not running as asserted above
+        unsafe { lua_resetthread(self.get_state().to_ptr()) };
+    }
+
+    /// Treats the thread as a generator which produces the values it yields until it returns
+    pub fn generator<R: FromLuauMulti>(self) -> Generator<'lua, R> {
+        Generator {
+            thread: self,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the values yielded by a thread, created by `LuauThread::generator`
+///
+/// Values returned by the thread are discarded and breakpoints are resumed past.
+pub struct Generator<'lua, R> {
+    thread: LuauThread<'lua>,
+    done: bool,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R: FromLuauMulti> Iterator for Generator<'_, R> {
+    type Item = Result<R, LuauError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.thread.resume::<_, R>(()) {
+                Ok(ResumeResult::Yielded(value)) => return Some(Ok(value)),
+                Ok(ResumeResult::Returned(_)) => self.done = true,
+                Ok(ResumeResult::Break) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
+mod tests {
+    use crate::{CoroutineStatus, Luau, LuauFunction, LuauLibs, ResumeResult};
+
+    fn function(luau: &Luau, source: &str) -> LuauFunction {
+        luau.chunk(source).into_function().unwrap()
+    }
+
+    #[test]
+    fn resume() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let thread = luau.new_thread();
+        thread.get_state().push(function(
+            &luau,
+            "local a = ... local b = coroutine.yield(a + 1) return a + b, 'done'",
+        ));
+
+        assert_eq!(thread.status(), CoroutineStatus::LUA_COSUS);
+        assert_eq!(
+            thread.resume::<_, f64>(1.0).unwrap(),
+            ResumeResult::Yielded(2.0)
+        );
+        assert_eq!(
+            thread.get_state().top(),
+            0,
+            "Expected yielded values to be moved"
+        );
+        assert_eq!(
+            thread.resume::<_, (f64, String)>(10.0).unwrap(),
+            ResumeResult::Returned((11.0, "done".to_string()))
+        );
+
+        assert!(thread.is_finished());
+        assert!(
+            thread.resume::<_, ()>(()).is_err(),
+            "Expected a finished thread to error"
+        );
+
+        thread.reset();
+        assert!(thread.is_reset());
+
+        thread.get_state().push(function(&luau, "error('boom')"));
+        assert!(!thread.is_finished());
+        assert!(thread.resume::<_, ()>(()).is_err());
+        assert_eq!(thread.status(), CoroutineStatus::LUA_COERR);
+        assert_eq!(luau.top(), 1, "Expected only the thread on the main stack");
+    }
+
+    #[test]
+    fn generator() {
+        let luau = Luau::default();
+
+        luau.load_libs(LuauLibs::ALL_LIBS);
+
+        let thread = luau.new_thread();
+        thread.get_state().push(function(
+            &luau,
+            "for i = 1, 3 do coroutine.yield(i * i) end return 'ignored'",
+        ));
+
+        let squares = thread
+            .generator::<f64>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(squares, vec![1.0, 4.0, 9.0]);
+
+        let thread = luau.new_thread();
+        thread
+            .get_state()
+            .push(function(&luau, "coroutine.yield(1) error('boom')"));
+
+        let mut generator = thread.generator::<f64>();
+
+        assert_eq!(generator.next().unwrap().unwrap(), 1.0);
+        assert!(generator.next().unwrap().is_err());
+        assert!(generator.next().is_none());
+    }
+}
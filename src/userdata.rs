@@ -1,27 +1,41 @@
 use std::{
     any::{Any, TypeId},
     cell::Cell,
+    collections::HashMap,
     error::Error,
+    ffi::{c_int, c_void},
     fmt::{Debug, Display},
+    marker::PhantomData,
     ops::{Deref, DerefMut},
-    ffi::c_void,
     ptr::drop_in_place,
 };
 
-use crate::ffi::{luauconf::LUA_UTAG_LIMIT, prelude::*};
+use crate::{
+    conversion::type_name,
+    error::guard_destructor,
+    ffi::{luauconf::LUA_UTAG_LIMIT, prelude::*},
+    ConversionError, FromLuau, FromLuauMulti, IntoLuau, IntoLuauMulti, Luau, LuauRef,
+};
 
 pub(crate) const UD_TAG: Tag = Tag(LUA_UTAG_LIMIT - 1);
+pub(crate) const FN_TAG: Tag = Tag(LUA_UTAG_LIMIT - 2);
+
+/// Tags from this value up to the tag limit are used internally and are never assigned to registered types
+pub(crate) const RESERVED_TAG_START: c_int = FN_TAG.0;
+
+/// Type id of the closure state behind Rust functions, which do not need to be `'static`
+pub(crate) struct RustFunction;
 
 #[derive(Debug)]
 #[repr(C)]
-pub(crate) struct Userdata<T: Any + ?Sized> {
+pub(crate) struct Userdata<T: ?Sized> {
     pub(crate) id: TypeId, // typeid of T
     pub(crate) count_cell: Cell<isize>,
     pub(crate) dtor: Option<unsafe fn(*mut Userdata<T>)>,
     pub(crate) inner: T,
 }
 
-impl<T: Any + ?Sized> Userdata<T> {
+impl<T: ?Sized> Userdata<T> {
     pub(crate) fn is<V: Any>(&self) -> bool {
         self.id == TypeId::of::<V>()
     }
@@ -48,9 +62,9 @@ impl Display for UserdataBorrowError {
 
 impl Error for UserdataBorrowError {}
 
-pub struct UserdataRef<T: Any>(*mut Userdata<T>);
+pub struct UserdataRef<T>(*mut Userdata<T>);
 
-impl<T: Any> UserdataRef<T> {
+impl<T> UserdataRef<T> {
     pub(crate) unsafe fn try_from_ptr(
         value: *mut Userdata<T>,
     ) -> Result<UserdataRef<T>, UserdataBorrowError> {
@@ -66,7 +80,7 @@ impl<T: Any> UserdataRef<T> {
     }
 }
 
-impl<T: Any> Deref for UserdataRef<T> {
+impl<T> Deref for UserdataRef<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         // SAFETY: cant be initialized with a null pointer
@@ -74,7 +88,7 @@ impl<T: Any> Deref for UserdataRef<T> {
     }
 }
 
-impl<T: Any> Drop for UserdataRef<T> {
+impl<T> Drop for UserdataRef<T> {
     fn drop(&mut self) {
         unsafe {
             let v = (*self.0).count_cell.get();
@@ -83,9 +97,9 @@ impl<T: Any> Drop for UserdataRef<T> {
     }
 }
 
-pub struct UserdataRefMut<T: Any>(*mut Userdata<T>);
+pub struct UserdataRefMut<T>(*mut Userdata<T>);
 
-impl<T: Any> UserdataRefMut<T> {
+impl<T> UserdataRefMut<T> {
     pub(crate) unsafe fn try_from_ptr(
         value: *mut Userdata<T>,
     ) -> Result<Self, UserdataBorrowError> {
@@ -102,7 +116,7 @@ impl<T: Any> UserdataRefMut<T> {
     }
 }
 
-impl<T: Any> Deref for UserdataRefMut<T> {
+impl<T> Deref for UserdataRefMut<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         // SAFETY: cant be initialized with a null pointer
@@ -110,14 +124,14 @@ impl<T: Any> Deref for UserdataRefMut<T> {
     }
 }
 
-impl<T: Any> DerefMut for UserdataRefMut<T> {
+impl<T> DerefMut for UserdataRefMut<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         // SAFETY: cant be initialized with a null pointer
         unsafe { &mut (*self.0).inner }
     }
 }
 
-impl<T: Any> Drop for UserdataRefMut<T> {
+impl<T> Drop for UserdataRefMut<T> {
     fn drop(&mut self) {
         unsafe { (*self.0).count_cell.set(0) }
     }
@@ -131,18 +145,502 @@ pub(crate) unsafe extern "C-unwind" fn dtor_rs_luau_userdata_callback(
 ) {
     let mut_self = &mut *(v as *mut Userdata<()>);
 
-    mut_self.dtor.inspect(|func| {
-        func(v as _);
-    });
+    if let Some(func) = mut_self.dtor {
+        guard_destructor(|| func(v as _));
+    }
+}
+
+// tagged userdata only hold values of T so the dtor field does not need to be read
+pub(crate) unsafe extern "C-unwind" fn dtor_tagged_userdata<T: Any>(
+    _: *mut _LuaState,
+    v: *mut c_void,
+) {
+    guard_destructor(|| drop_userdata::<T>(v as _));
 }
 
 // needs to invoke drop_in_place for T
-pub(crate) unsafe fn drop_userdata<T: Any + ?Sized>(ud: *mut Userdata<T>) {
+pub(crate) unsafe fn drop_userdata<T: ?Sized>(ud: *mut Userdata<T>) {
     drop_in_place(&raw mut (*ud).inner);
 }
 
-#[cfg(test)]
+/// Metamethods which can be implemented for a userdata type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetaMethod {
+    Index,
+    NewIndex,
+    Call,
+    Concat,
+    Unm,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IDiv,
+    Mod,
+    Pow,
+    Len,
+    Eq,
+    Lt,
+    Le,
+    ToString,
+    Iter,
+}
+
+impl MetaMethod {
+    /// Returns the metatable key of the metamethod
+    pub const fn name(self) -> &'static str {
+        match self {
+            MetaMethod::Index => "__index",
+            MetaMethod::NewIndex => "__newindex",
+            MetaMethod::Call => "__call",
+            MetaMethod::Concat => "__concat",
+            MetaMethod::Unm => "__unm",
+            MetaMethod::Add => "__add",
+            MetaMethod::Sub => "__sub",
+            MetaMethod::Mul => "__mul",
+            MetaMethod::Div => "__div",
+            MetaMethod::IDiv => "__idiv",
+            MetaMethod::Mod => "__mod",
+            MetaMethod::Pow => "__pow",
+            MetaMethod::Len => "__len",
+            MetaMethod::Eq => "__eq",
+            MetaMethod::Lt => "__lt",
+            MetaMethod::Le => "__le",
+            MetaMethod::ToString => "__tostring",
+            MetaMethod::Iter => "__iter",
+        }
+    }
+}
+
+impl Display for MetaMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A Rust type which can be pushed to Luau with methods, fields and metamethods
+pub trait UserData: Any + Sized {
+    /// Registers the methods, fields and metamethods of the type, called once per state when the metatable is created
+    fn register(_registry: &mut UserDataRegistry<Self>) {}
+}
+
+type Callback = Box<dyn FnMut(&Luau) -> c_int>;
+
+/// Collects the methods, fields and metamethods of a `UserData` type
+pub struct UserDataRegistry<T> {
+    methods: Vec<(String, Callback)>,
+    getters: HashMap<String, Callback>,
+    setters: HashMap<String, Callback>,
+    meta: Vec<(MetaMethod, Callback)>,
+    _marker: PhantomData<T>,
+}
+
+/// Pushes the results of a userdata callback or raises its error
+fn finish<R: IntoLuauMulti, E: Into<Box<dyn Error>>>(luau: &Luau, result: Result<R, E>) -> c_int {
+    match result {
+        Ok(results) => results.push_multi(luau),
+        Err(err) => luau.raise_external(err.into()),
+    }
+}
+
+fn args<A: FromLuauMulti>(luau: &Luau, idx: c_int) -> A {
+    match A::from_luau_multi(luau, idx) {
+        Ok(args) => args,
+        Err(err) => luau.argument_error(&err),
+    }
+}
+
+/// Borrows the userdata of type T which the callback was invoked on at the first argument
+fn borrow_self<T: Any>(luau: &Luau) -> UserdataRef<T> {
+    match luau.try_borrow_userdata::<T>(1) {
+        Some(Ok(this)) => this,
+        Some(Err(err)) => luau.raise_external(Box::new(err)),
+        None => luau.argument_error(&ConversionError::new(
+            1,
+            luau.type_of_or_none(1),
+            std::any::type_name::<T>(),
+        )),
+    }
+}
+
+fn borrow_self_mut<T: Any>(luau: &Luau) -> UserdataRefMut<T> {
+    match luau.try_borrow_userdata_mut::<T>(1) {
+        Some(Ok(this)) => this,
+        Some(Err(err)) => luau.raise_external(Box::new(err)),
+        None => luau.argument_error(&ConversionError::new(
+            1,
+            luau.type_of_or_none(1),
+            std::any::type_name::<T>(),
+        )),
+    }
+}
+
+impl<T: UserData> UserDataRegistry<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            methods: Vec::new(),
+            getters: HashMap::new(),
+            setters: HashMap::new(),
+            meta: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds a method called as `value:name(...)` which borrows the value immutably
+    pub fn add_method<A, R, E, F>(&mut self, name: impl Into<String>, mut method: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, &T, A) -> Result<R, E> + 'static,
+    {
+        self.methods.push((
+            name.into(),
+            Box::new(move |luau| {
+                let this = borrow_self::<T>(luau);
+                let args = args(luau, 2);
+
+                finish(luau, method(luau, &this, args))
+            }),
+        ));
+    }
+
+    /// Adds a method called as `value:name(...)` which borrows the value mutably
+    pub fn add_method_mut<A, R, E, F>(&mut self, name: impl Into<String>, mut method: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, &mut T, A) -> Result<R, E> + 'static,
+    {
+        self.methods.push((
+            name.into(),
+            Box::new(move |luau| {
+                let mut this = borrow_self_mut::<T>(luau);
+                let args = args(luau, 2);
+
+                finish(luau, method(luau, &mut this, args))
+            }),
+        ));
+    }
+
+    /// Adds a field read as `value.name`
+    pub fn add_field_getter<R, E, F>(&mut self, name: impl Into<String>, mut getter: F)
+    where
+        R: IntoLuau,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, &T) -> Result<R, E> + 'static,
+    {
+        self.getters.insert(
+            name.into(),
+            Box::new(move |luau| {
+                let this = borrow_self::<T>(luau);
+
+                finish(luau, getter(luau, &this))
+            }),
+        );
+    }
+
+    /// Adds a field assigned as `value.name = v`
+    pub fn add_field_setter<V, E, F>(&mut self, name: impl Into<String>, mut setter: F)
+    where
+        V: FromLuau,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, &mut T, V) -> Result<(), E> + 'static,
+    {
+        self.setters.insert(
+            name.into(),
+            Box::new(move |luau| {
+                let mut this = borrow_self_mut::<T>(luau);
+                let value = args(luau, 3);
+
+                finish(luau, setter(luau, &mut this, value))
+            }),
+        );
+    }
+
+    /// Adds a metamethod which borrows the value at the first argument with the remaining arguments converted to `A`
+    ///
+    /// `Index` and `NewIndex` metamethods are invoked for keys which are not registered methods or fields.
+    pub fn add_meta_method<A, R, E, F>(&mut self, meta: MetaMethod, mut method: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, &T, A) -> Result<R, E> + 'static,
+    {
+        self.meta.push((
+            meta,
+            Box::new(move |luau| {
+                let this = borrow_self::<T>(luau);
+                let args = args(luau, 2);
+
+                finish(luau, method(luau, &this, args))
+            }),
+        ));
+    }
+
+    /// Adds a metamethod which converts all of its arguments to `A`
+    ///
+    /// Useful for operators where the userdata may not be the first operand.
+    pub fn add_meta_function<A, R, E, F>(&mut self, meta: MetaMethod, mut function: F)
+    where
+        A: FromLuauMulti,
+        R: IntoLuauMulti,
+        E: Into<Box<dyn Error>>,
+        F: FnMut(&Luau, A) -> Result<R, E> + 'static,
+    {
+        self.meta.push((
+            meta,
+            Box::new(move |luau| {
+                let args = args(luau, 1);
+
+                finish(luau, function(luau, args))
+            }),
+        ));
+    }
+
+    /// Pushes a metatable built from the registered callbacks
+    pub(crate) fn push_metatable(mut self, luau: &Luau) {
+        let mut index = None;
+        let mut new_index = None;
+
+        luau.create_table();
+        let metatable = luau.top();
+
+        for (meta, callback) in self.meta {
+            match meta {
+                MetaMethod::Index => index = Some(callback),
+                MetaMethod::NewIndex => new_index = Some(callback),
+                _ => {
+                    luau.push_function(callback, None, 0);
+                    luau.set_field(metatable, meta.name());
+                }
+            }
+        }
+
+        luau.create_table();
+
+        for (name, callback) in self.methods {
+            luau.push_function(callback, None, 0);
+            luau.raw_set_field(-2, &name);
+        }
+
+        if self.getters.is_empty() && index.is_none() {
+            // methods can be looked up directly from the table
+            luau.set_field(metatable, "__index");
+        } else {
+            let methods = LuauRef::new(luau, -1);
+            luau.pop(1);
+
+            let mut getters = self.getters;
+
+            luau.push_function(
+                move |luau| {
+                    if luau.is_string(2) {
+                        let getter = luau
+                            .to_str(2)
+                            .and_then(Result::ok)
+                            .and_then(|key| getters.get_mut(key));
+
+                        if let Some(getter) = getter {
+                            return getter(luau);
+                        }
+                    }
+
+                    luau.push(&methods);
+                    // SAFETY: the methods table is on the top of the stack and the key is an argument
+                    unsafe {
+                        lua_pushvalue(luau.state, 2);
+                        lua_rawget(luau.state, -2);
+                    }
+
+                    if !luau.is_nil(-1) {
+                        return 1;
+                    }
+
+                    luau.pop(2);
+
+                    match index.as_mut() {
+                        Some(index) => index(luau),
+                        None => {
+                            luau.push_nil();
+                            1
+                        }
+                    }
+                },
+                Some(c"__index"),
+                0,
+            );
+            luau.set_field(metatable, "__index");
+        }
+
+        if !self.setters.is_empty() || new_index.is_some() {
+            let userdata_name = std::any::type_name::<T>();
+
+            luau.push_function(
+                move |luau| {
+                    if luau.is_string(2) {
+                        let setter = luau
+                            .to_str(2)
+                            .and_then(Result::ok)
+                            .and_then(|key| self.setters.get_mut(key));
+
+                        if let Some(setter) = setter {
+                            return setter(luau);
+                        }
+                    }
+
+                    match new_index.as_mut() {
+                        Some(new_index) => new_index(luau),
+                        None => {
+                            let key = match luau.type_of(2) {
+                                LuauType::LUA_TSTRING => String::from_utf8_lossy(
+                                    luau.to_str_slice(2).unwrap_or_default(),
+                                )
+                                .into_owned(),
+                                ty => type_name(ty).to_string(),
+                            };
+
+                            luau.push_string(format!(
+                                "cannot assign to field '{key}' of {userdata_name}"
+                            ));
+                            luau.error()
+                        }
+                    }
+                },
+                Some(c"__newindex"),
+                0,
+            );
+            luau.set_field(metatable, "__newindex");
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compiler"))]
 mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        compile::Compiler, ffi::prelude::Tag, Luau, LuauError, LuauFunction, LuauLibs, LuauRef,
+        MetaMethod, UserData, UserDataRegistry,
+    };
+
     #[test]
     fn exec_test() {}
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    impl UserData for Point {
+        fn register(registry: &mut UserDataRegistry<Self>) {
+            registry.add_method("length", |_, this, ()| {
+                Ok::<_, LuauError>(this.x.hypot(this.y))
+            });
+            registry.add_method_mut("scale", |_, this, n: f64| {
+                this.x *= n;
+                this.y *= n;
+                Ok::<_, LuauError>(())
+            });
+            registry.add_field_getter("x", |_, this| Ok::<_, LuauError>(this.x));
+            registry.add_field_setter("x", |_, this, x: f64| {
+                this.x = x;
+                Ok::<_, LuauError>(())
+            });
+            registry.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+                Ok::<_, LuauError>(format!("({}, {})", this.x, this.y))
+            });
+            registry.add_meta_method(MetaMethod::Add, |_, this, n: f64| {
+                Ok::<_, LuauError>(this.x + this.y + n)
+            });
+        }
+    }
+
+    #[test]
+    fn methods_and_fields() {
+        let luau = Luau::default();
+        let compiler = Compiler::new();
+
+        let bytecode = compiler.compile(
+            r#"
+            local p = ...
+            p:scale(2)
+            p.x = p.x + 1
+            return p:length(), tostring(p), p + 1
+            "#,
+        );
+
+        luau.load(None, bytecode.bytecode().unwrap(), 0).unwrap();
+        let chunk = luau.get::<LuauFunction>(-1).unwrap();
+        luau.pop(1);
+
+        luau.create_userdata(Point { x: 1.0, y: 2.0 });
+        let point = luau.get::<LuauRef>(-1).unwrap();
+        luau.pop(1);
+
+        let (length, string, sum) = chunk.call::<_, (f64, String, f64)>(&point).unwrap();
+
+        assert_eq!(length, 5.0);
+        assert_eq!(string, "(3, 4)");
+        assert_eq!(sum, 8.0);
+        assert_eq!(
+            point.type_of(),
+            crate::LuauType::LUA_TUSERDATA,
+            "Expected the point to remain a userdata"
+        );
+        assert_eq!(luau.top(), 0, "Expected the stack to be balanced");
+    }
+
+    #[test]
+    fn tags() {
+        let luau = Luau::default();
+        luau.load_libs(LuauLibs::LIB_BASE);
+
+        let counter = Rc::new(());
+
+        // values pushed before registering keep the shared tag
+        luau.push_userdata(Point { x: 0.0, y: 0.0 });
+
+        assert_eq!(luau.register_userdata::<Point>("Point"), Some(Tag(1)));
+        assert_eq!(
+            luau.register_userdata_type::<Rc<()>>("Counter"),
+            Some(Tag(2))
+        );
+        assert_eq!(luau.register_userdata::<Point>("Point"), Some(Tag(1)));
+        assert_eq!(luau.userdata_type_names(), vec!["Point", "Counter"]);
+        assert_eq!(luau.top(), 1, "Expected registering to pop the metatable");
+
+        luau.push_userdata(Point { x: 3.0, y: 4.0 });
+        luau.push_userdata(counter.clone());
+
+        assert!(luau.is_userdata::<Point>(1));
+        assert!(luau.is_userdata::<Point>(2));
+        assert!(!luau.is_userdata::<Point>(3));
+        assert_eq!(luau.borrow_userdata::<Point>(2).unwrap().x, 3.0);
+
+        let compiler = Compiler::new().set_userdata_types(luau.userdata_type_names());
+        let bytecode = compiler.compile("local p = ... return typeof(p), p:length()");
+
+        luau.load(None, bytecode.bytecode().unwrap(), 0).unwrap();
+        // the counter is passed as an extra argument so the point is the first
+        luau.shift(2);
+        luau.call(2, 2).unwrap();
+
+        assert_eq!(
+            luau.get::<(String, f64)>(-2),
+            Ok(("Point".to_string(), 5.0))
+        );
+        luau.pop(3);
+
+        assert_eq!(Rc::strong_count(&counter), 2);
+        drop(luau);
+        assert_eq!(
+            Rc::strong_count(&counter),
+            1,
+            "Expected the tag dtor to drop the value"
+        );
+    }
 }